@@ -1,4 +1,4 @@
-use std::{fs::File, io::BufRead, io::BufReader};
+use std::{fmt::Display, fs::File, io::BufRead, io::BufReader};
 
 /// input is lines of numbers
 fn read_input() -> Vec<Vec<u32>> {
@@ -31,137 +31,137 @@ enum Dir {
     Dec,
 }
 
-/// line is safe if:
-///     - values are all increasing or decreasing
-///     - adjacent values are at least 1, at most 3 apart
-///
-/// Works by retrying with first dropping the left, then the right value
-/// This one does not work for 7,5,6,7,8 since break_idx-1 should have been deleted
-fn safe(line: &[u32], retry: bool) -> u32 {
-    assert!(line.len() >= 2);
-
-    let mut result: u32 = 1;
-    let mut left_break_idx = 0;
+/// line is safe if at most one level has to be removed to make it safe
+fn maybe_safe(line: &[u32]) -> bool {
+    !matches!(explain(line), Verdict::Unsafe(_))
+}
 
-    let direction = match (line[0], line[1]) {
-        (left, right) if left < right => Dir::Inc,
-        (left, right) if left > right => Dir::Dec,
-        _ if retry => return safe(&line[1..], false),
-        _ => return 0,
-    };
-    for (left_idx, pair) in line.windows(2).enumerate() {
-        let [left, right] = pair else {
-            panic!("no pair found")
-        };
-        match left.abs_diff(*right) {
-            1..=3 => (),
-            _ => {
-                result = 0;
-                left_break_idx = left_idx;
-                break;
-            }
-        }
-        match direction {
-            Dir::Inc if left > right => {
-                result = 0;
-                left_break_idx = left_idx;
-                break;
-            }
-            Dir::Dec if left < right => {
-                result = 0;
-                left_break_idx = left_idx;
-                break;
-            }
-            _ => (),
-        }
-    }
-    if result == 0 && retry {
-        assert!(
-            left_break_idx != line.len() - 1,
-            "{line:?}, {left_break_idx}",
-        );
+fn is_safe(line: &[u32]) -> bool {
+    check(line).is_ok()
+}
 
-        // retry with left dropped
-        let retry_line = [
-            line[0..left_break_idx].to_vec(),
-            line[left_break_idx + 1..].to_vec(),
-        ]
-        .concat();
-        if safe(&retry_line, false) == 1 {
-            return 1;
-        }
+/// Rule a pair of adjacent levels can break
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Rule {
+    /// Levels switched from increasing to decreasing or vice versa
+    DirectionChange,
+    /// Levels are more than 3 apart
+    StepTooLarge,
+    /// Levels are equal
+    Duplicate,
+}
 
-        // retry with right dropped
-        if left_break_idx == line.len() - 2 {
-            // drop last element in array
-            safe(&line[0..left_break_idx + 1], false)
-        } else {
-            safe(
-                &[
-                    line[0..left_break_idx + 1].to_vec(),
-                    line[left_break_idx + 2..].to_vec(),
-                ]
-                .concat(),
-                false,
-            )
+impl Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Rule::DirectionChange => write!(f, "direction change"),
+            Rule::StepTooLarge => write!(f, "step too large"),
+            Rule::Duplicate => write!(f, "duplicate"),
         }
-    } else {
-        result
     }
 }
 
-fn maybe_safer(line: &[u32]) -> bool {
-    let result = maybe_safe(line);
-    let old = safe(line, true) != 0;
-    assert_eq!(result, old, "{line:?}");
-    result
+/// First pair of levels that breaks a rule, `idx` is the index of the left level
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Violation {
+    idx: usize,
+    left: u32,
+    right: u32,
+    rule: Rule,
 }
 
-fn maybe_safe(line: &[u32]) -> bool {
-    if is_safe(line) {
-        return true;
+impl Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} at {}..={} ({} {})",
+            self.rule,
+            self.idx,
+            self.idx + 1,
+            self.left,
+            self.right
+        )
     }
+}
 
-    for idx in 0..line.len() {
-        let retry_line = match idx {
-            0 => line[1..].to_vec(),
-            idx if idx == line.len() - 1 => line[0..idx].to_vec(),
-            idx => [line[0..idx].to_vec(), line[idx + 1..].to_vec()].concat(),
-        };
-        if is_safe(&retry_line) {
-            return true;
+/// Outcome of checking a report with the Problem Dampener
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Verdict {
+    Safe,
+    /// Safe after removing the level at `removed`
+    Dampened { removed: usize, violation: Violation },
+    Unsafe(Violation),
+}
+
+impl Display for Verdict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Verdict::Safe => write!(f, "safe"),
+            Verdict::Dampened { removed, violation } => {
+                write!(f, "safe, removed level {removed}, {violation}")
+            }
+            Verdict::Unsafe(violation) => write!(f, "unsafe, {violation}"),
         }
     }
-    false
 }
 
-fn is_safe(line: &[u32]) -> bool {
+/// Find the first pair of levels breaking a rule
+///
+/// The direction is determined by the first pair.
+fn check(line: &[u32]) -> Result<(), Violation> {
     assert!(line.len() >= 2);
 
     let direction = match (line[0], line[1]) {
         (left, right) if left < right => Dir::Inc,
         (left, right) if left > right => Dir::Dec,
-        _ => return false,
+        _ => Dir::Inc, // Duplicate, reported below
     };
-    for pair in line.windows(2) {
+    for (idx, pair) in line.windows(2).enumerate() {
         let [left, right] = pair else {
             panic!("no pair found")
         };
-        match left.abs_diff(*right) {
-            1..=3 => (),
-            _ => return false,
-        }
-        match direction {
-            Dir::Inc if left > right => return false,
-            Dir::Dec if left < right => return false,
-            _ => (),
+        let rule = match (left.abs_diff(*right), &direction) {
+            (0, _) => Rule::Duplicate,
+            (1..=3, Dir::Inc) if left > right => Rule::DirectionChange,
+            (1..=3, Dir::Dec) if left < right => Rule::DirectionChange,
+            (1..=3, _) => continue,
+            _ => Rule::StepTooLarge,
+        };
+        return Err(Violation {
+            idx,
+            left: *left,
+            right: *right,
+            rule,
+        });
+    }
+    Ok(())
+}
+
+/// Explain why a report is (un)safe
+///
+/// Only the levels around the first violation are candidates for removal:
+/// removing any other level leaves the violating pair, and its direction, intact.
+fn explain(line: &[u32]) -> Verdict {
+    let Err(violation) = check(line) else {
+        return Verdict::Safe;
+    };
+
+    for removed in violation.idx.saturating_sub(1)..=violation.idx + 1 {
+        let retry_line = [&line[0..removed], &line[removed + 1..]].concat();
+        if retry_line.len() < 2 || check(&retry_line).is_ok() {
+            return Verdict::Dampened { removed, violation };
         }
     }
-    true
+    Verdict::Unsafe(violation)
 }
 
 fn main() {
     let input = read_input();
+    if std::env::args().any(|arg| arg == "--explain") {
+        for line in &input {
+            println!("{line:?}: {}", explain(line));
+        }
+    }
     println!("{}", puzzle_1(&input));
     println!("{}", puzzle_2(&input));
 }
@@ -210,4 +210,92 @@ mod test {
         ];
         assert_eq!(crate::puzzle_2(&input), 14);
     }
+
+    #[test]
+    fn test_explain() {
+        use crate::{explain, Rule, Verdict, Violation};
+
+        assert_eq!(explain(&[7, 6, 4, 2, 1]), Verdict::Safe);
+        assert_eq!(
+            explain(&[1, 2, 7, 8, 9]),
+            Verdict::Unsafe(Violation {
+                idx: 1,
+                left: 2,
+                right: 7,
+                rule: Rule::StepTooLarge
+            })
+        );
+        assert_eq!(
+            explain(&[1, 3, 2, 4, 5]),
+            Verdict::Dampened {
+                removed: 1,
+                violation: Violation {
+                    idx: 1,
+                    left: 3,
+                    right: 2,
+                    rule: Rule::DirectionChange
+                }
+            }
+        );
+        assert_eq!(
+            explain(&[8, 6, 4, 4, 1]),
+            Verdict::Dampened {
+                removed: 2,
+                violation: Violation {
+                    idx: 2,
+                    left: 4,
+                    right: 4,
+                    rule: Rule::Duplicate
+                }
+            }
+        );
+    }
+
+    /// Reference implementation, tries removing every level
+    fn brute_force_safe(line: &[u32]) -> bool {
+        crate::is_safe(line)
+            || (0..line.len()).any(|idx| {
+                let retry_line = [&line[0..idx], &line[idx + 1..]].concat();
+                retry_line.len() < 2 || crate::is_safe(&retry_line)
+            })
+    }
+
+    /// All reports of `len` levels with values in `1..=max`
+    fn generate(len: usize, max: u32) -> Vec<Vec<u32>> {
+        let mut reports = vec![];
+        let mut line = vec![1; len];
+        loop {
+            reports.push(line.clone());
+            let Some(idx) = line.iter().position(|level| *level < max) else {
+                return reports;
+            };
+            line[..idx].fill(1);
+            line[idx] += 1;
+        }
+    }
+
+    #[test]
+    fn test_maybe_safe_differential() {
+        let disagreements: Vec<_> = (2..=6)
+            .flat_map(|len| generate(len, 6))
+            .filter(|line| crate::maybe_safe(line) != brute_force_safe(line))
+            .collect();
+        assert!(disagreements.is_empty(), "{disagreements:?}");
+    }
+
+    /// Reports the old retry heuristic got wrong
+    #[test]
+    fn test_maybe_safe_regressions() {
+        for line in [
+            vec![7, 5, 6, 7, 8],
+            vec![1, 3, 2, 1],
+            vec![1, 4, 3, 2, 1],
+            vec![2, 1, 2, 3],
+            vec![2, 1, 2, 5, 6],
+            vec![2, 5, 4, 3, 1],
+            vec![3, 1, 3, 4, 5],
+        ] {
+            assert!(crate::maybe_safe(&line), "{line:?}");
+        }
+    }
 }
@@ -1,81 +1,823 @@
-use std::{collections::BTreeMap, fs};
+use std::{
+    collections::VecDeque,
+    fmt::{Debug, Display},
+    fs::File,
+    io::{self, Read, Write},
+};
 
-use regex::Regex;
+/// Bytes read from the memory dump at a time
+const CHUNK_SIZE: usize = 64 * 1024;
 
-fn read_input() -> String {
-    fs::read_to_string("input.txt").expect("Could not read input file")
+fn read_input() -> File {
+    File::open("input.txt").expect("Could not read input file")
 }
 
 /// find and execute "mul(a,b)"
-fn puzzle_1(line: &str) -> u32 {
-    find_mul(line)
+fn puzzle_1(memory: impl Read) -> u128 {
+    find_mul(memory)
 }
 
-fn find_mul(line: &str) -> u32 {
-    let re = Regex::new(r"mul\((?<lhs>[0-9]{1,3}),(?<rhs>[0-9]{1,3})\)").expect("valid regex");
+fn find_mul(memory: impl Read) -> u128 {
+    run_stream(memory, &[&Mul], &Grammar::default(), CHUNK_SIZE)
+        .unwrap_or_else(|err| panic!("{err}"))
+        .acc
+}
 
-    re.captures_iter(line).fold(0, |acc, hit| {
-        acc + (hit["lhs"].parse::<u32>().unwrap() * hit["rhs"].parse::<u32>().unwrap())
-    })
+fn puzzle_2(memory: impl Read) -> u128 {
+    find_maybe_mul(memory)
 }
 
-fn puzzle_2(line: &str) -> u32 {
-    find_maybe_mul(line)
+fn find_maybe_mul(memory: impl Read) -> u128 {
+    run_stream(memory, &[&Mul, &Do, &Dont], &Grammar::default(), CHUNK_SIZE)
+        .unwrap_or_else(|err| panic!("{err}"))
+        .acc
 }
 
-fn find_maybe_mul(line: &str) -> u32 {
-    let mul_re = Regex::new(r"mul\((?<lhs>[0-9]{1,3}),(?<rhs>[0-9]{1,3})\)").expect("valid regex");
-    let do_re = Regex::new(r"do\(\)").expect("valid regex");
-    let dont_re = Regex::new(r"don't\(\)").expect("valid regex");
+/// State the instructions operate on
+#[derive(Debug, PartialEq, Eq)]
+struct Machine {
+    enabled: bool,
+    acc: u128,
+}
 
-    // Ordered map of do's and dont's
-    let mut map = BTreeMap::new();
-    do_re.find_iter(line).for_each(|hit| {
-        map.insert(hit.start(), true);
-    });
-    dont_re.find_iter(line).for_each(|hit| {
-        map.insert(hit.start(), false);
-    });
-    let mut acc = 0;
-    for hit in mul_re.captures_iter(line) {
-        let location = hit.name("lhs").unwrap().start();
-
-        // find the last do/don't before the location
-        let mut enabled = true;
-        for (idx, enable) in map.iter() {
-            // if idx is > location, we past the hit, the previous loop determines our enable
-            if *idx > location {
-                break;
+impl Default for Machine {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            acc: 0,
+        }
+    }
+}
+
+/// The accumulator of the [Machine] overflowed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Overflow;
+
+/// Instruction that can be recognised in memory and executed on a [Machine]
+///
+/// In memory an instruction looks like `name(a,b)` with `arity` operands, see [Grammar].
+trait Instruction: Debug {
+    fn name(&self) -> &'static str;
+    fn arity(&self) -> usize;
+    fn execute(&self, machine: &mut Machine, operands: &[u64]) -> Result<(), Overflow>;
+}
+
+/// `mul(a,b)`, adds a * b to the accumulator when enabled
+#[derive(Debug)]
+struct Mul;
+
+impl Instruction for Mul {
+    fn name(&self) -> &'static str {
+        "mul"
+    }
+    fn arity(&self) -> usize {
+        2
+    }
+    fn execute(&self, machine: &mut Machine, operands: &[u64]) -> Result<(), Overflow> {
+        if machine.enabled {
+            let product = operands[0] as u128 * operands[1] as u128;
+            machine.acc = machine.acc.checked_add(product).ok_or(Overflow)?;
+        }
+        Ok(())
+    }
+}
+
+/// `do()`, enables future instructions
+#[derive(Debug)]
+struct Do;
+
+impl Instruction for Do {
+    fn name(&self) -> &'static str {
+        "do"
+    }
+    fn arity(&self) -> usize {
+        0
+    }
+    fn execute(&self, machine: &mut Machine, _operands: &[u64]) -> Result<(), Overflow> {
+        machine.enabled = true;
+        Ok(())
+    }
+}
+
+/// `don't()`, disables future instructions
+#[derive(Debug)]
+struct Dont;
+
+impl Instruction for Dont {
+    fn name(&self) -> &'static str {
+        "don't"
+    }
+    fn arity(&self) -> usize {
+        0
+    }
+    fn execute(&self, machine: &mut Machine, _operands: &[u64]) -> Result<(), Overflow> {
+        machine.enabled = false;
+        Ok(())
+    }
+}
+
+/// How operands of an instruction are written in memory
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Grammar {
+    /// Maximum number of digits of an operand, at most 19 so every operand fits a `u64`
+    max_digits: usize,
+    /// Bytes allowed between two operands
+    separators: Vec<u8>,
+    /// Number of spaces or tabs allowed before and after each operand
+    whitespace: usize,
+}
+
+impl Default for Grammar {
+    /// `mul(a,b)` with operands of 1-3 digits
+    fn default() -> Self {
+        Self {
+            max_digits: 3,
+            separators: vec![b','],
+            whitespace: 0,
+        }
+    }
+}
+
+impl Grammar {
+    /// Longest `instruction` can be in memory
+    fn max_len(&self, instruction: &dyn Instruction) -> usize {
+        let operand = self.max_digits + 2 * self.whitespace;
+        instruction.name().len() + 2 + instruction.arity() * (operand + 1)
+    }
+}
+
+/// Location in memory, lines and columns start at 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Position {
+    offset: usize,
+    line: usize,
+    column: usize,
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Self {
+            offset: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+}
+
+impl Position {
+    /// Move past `byte`
+    fn advance(&mut self, byte: u8) {
+        self.offset += 1;
+        if byte == b'\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+    }
+}
+
+/// Why text starting with an instruction name is not a valid instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Rejection {
+    /// `found` is `None` at the end of memory
+    Unexpected {
+        expected: &'static str,
+        found: Option<u8>,
+    },
+    /// Operand has more than `max_digits` digits
+    OperandTooLong { max_digits: usize },
+}
+
+impl Display for Rejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Rejection::Unexpected {
+                expected,
+                found: Some(found),
+            } => write!(f, "expected {expected} found {:?}", *found as char),
+            Rejection::Unexpected {
+                expected,
+                found: None,
+            } => write!(f, "expected {expected} found end of memory"),
+            Rejection::OperandTooLong { max_digits } => {
+                write!(f, "operand longer than {max_digits} digits")
+            }
+        }
+    }
+}
+
+/// Instruction recognised in memory, or a near-miss starting with an instruction name
+#[derive(Debug)]
+struct Token<'a> {
+    position: Position,
+    /// Text of the instruction, for a near-miss up to and including the offending byte
+    text: String,
+    instruction: &'a dyn Instruction,
+    operands: Result<Vec<u64>, Rejection>,
+}
+
+/// Single pass scanner yielding every instruction and near-miss in corrupted memory
+struct Lexer<'m, 'i> {
+    memory: &'m [u8],
+    instructions: &'i [&'i dyn Instruction],
+    grammar: &'i Grammar,
+    pos: usize,
+    /// Position of `pos` in the whole memory
+    position: Position,
+    /// No instructions are matched starting at or after `end`
+    end: usize,
+}
+
+impl<'m, 'i> Lexer<'m, 'i> {
+    fn new(
+        memory: &'m [u8],
+        instructions: &'i [&'i dyn Instruction],
+        grammar: &'i Grammar,
+    ) -> Self {
+        Self {
+            memory,
+            instructions,
+            grammar,
+            pos: 0,
+            position: Position::default(),
+            end: memory.len(),
+        }
+    }
+
+    /// Lex a chunk of memory that starts at `position` and is continued after its end
+    ///
+    /// Stops before any instruction that could be cut off by the end of the chunk,
+    /// `pos` is where lexing should resume once more memory is available.
+    fn partial(
+        memory: &'m [u8],
+        instructions: &'i [&'i dyn Instruction],
+        grammar: &'i Grammar,
+        position: Position,
+    ) -> Self {
+        let max_len = instructions
+            .iter()
+            .map(|instruction| grammar.max_len(*instruction))
+            .max()
+            .unwrap_or(1);
+        Self {
+            position,
+            end: (memory.len() + 1).saturating_sub(max_len),
+            ..Self::new(memory, instructions, grammar)
+        }
+    }
+
+    /// Parse `instruction` at `pos`
+    ///
+    /// Returns the operands, or why parsing failed, and the position after the parsed text.
+    fn parse(
+        &self,
+        instruction: &dyn Instruction,
+        pos: usize,
+    ) -> (Result<Vec<u64>, Rejection>, usize) {
+        let unexpected = |pos: usize, expected: &'static str| {
+            let found = self.memory.get(pos).copied();
+            (Err(Rejection::Unexpected { expected, found }), pos + 1)
+        };
+        let skip_whitespace = |pos: usize| {
+            pos + self.memory[pos..]
+                .iter()
+                .take(self.grammar.whitespace)
+                .take_while(|byte| matches!(byte, b' ' | b'\t'))
+                .count()
+        };
+
+        let mut pos = pos + instruction.name().len();
+        if self.memory.get(pos) != Some(&b'(') {
+            return unexpected(pos, "'('");
+        }
+        pos += 1;
+
+        let mut operands = Vec::with_capacity(instruction.arity());
+        for idx in 0..instruction.arity() {
+            if idx > 0 {
+                match self.memory.get(pos) {
+                    Some(byte) if self.grammar.separators.contains(byte) => pos += 1,
+                    _ => return unexpected(pos, "separator"),
+                }
+            }
+            pos = skip_whitespace(pos);
+            let digits = self.memory[pos..]
+                .iter()
+                .take(self.grammar.max_digits + 1)
+                .take_while(|byte| byte.is_ascii_digit())
+                .count();
+            if digits == 0 {
+                return unexpected(pos, "digit");
+            }
+            if digits > self.grammar.max_digits {
+                let max_digits = self.grammar.max_digits;
+                return (Err(Rejection::OperandTooLong { max_digits }), pos + digits);
             }
-            enabled = *enable;
+            let operand = self.memory[pos..pos + digits]
+                .iter()
+                .fold(0, |acc, byte| acc * 10 + (byte - b'0') as u64);
+            operands.push(operand);
+            pos = skip_whitespace(pos + digits);
         }
-        if enabled {
-            acc += hit["lhs"].parse::<u32>().unwrap() * hit["rhs"].parse::<u32>().unwrap();
+
+        if self.memory.get(pos) != Some(&b')') {
+            return unexpected(pos, "')'");
         }
+        (Ok(operands), pos + 1)
+    }
+
+    /// Move `pos` forward to `to`
+    fn advance(&mut self, to: usize) {
+        for byte in &self.memory[self.pos..to] {
+            self.position.advance(*byte);
+        }
+        self.pos = to;
     }
-    acc
+}
+
+impl<'i> Iterator for Lexer<'_, 'i> {
+    type Item = Token<'i>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pos < self.end {
+            let offset = self.pos;
+            // Furthest reaching near-miss at this offset
+            let mut near_miss = None;
+            for instruction in self.instructions {
+                if !self.memory[offset..].starts_with(instruction.name().as_bytes()) {
+                    continue;
+                }
+                let (operands, end) = self.parse(*instruction, offset);
+                let end = end.min(self.memory.len());
+                let text = String::from_utf8_lossy(&self.memory[offset..end]).into_owned();
+                match operands {
+                    Ok(operands) => {
+                        let position = self.position;
+                        self.advance(end);
+                        return Some(Token {
+                            position,
+                            text,
+                            instruction: *instruction,
+                            operands: Ok(operands),
+                        });
+                    }
+                    Err(rejection) => {
+                        if near_miss
+                            .as_ref()
+                            .is_none_or(|token: &Token| token.text.len() < text.len())
+                        {
+                            near_miss = Some(Token {
+                                position: self.position,
+                                text,
+                                instruction: *instruction,
+                                operands: Err(rejection),
+                            });
+                        }
+                    }
+                }
+            }
+            self.advance(offset + 1);
+            if near_miss.is_some() {
+                return near_miss;
+            }
+        }
+        None
+    }
+}
+
+/// Streams instructions from a reader in fixed-size chunks
+///
+/// Only the current chunk and the tail of the previous chunk are kept in memory,
+/// the tail holds any instruction split across the chunk boundary.
+struct Scanner<'i, R> {
+    reader: R,
+    instructions: &'i [&'i dyn Instruction],
+    grammar: &'i Grammar,
+    chunk_size: usize,
+    buffer: Vec<u8>,
+    /// Position of the start of `buffer`
+    position: Position,
+    tokens: VecDeque<Token<'i>>,
+    eof: bool,
+}
+
+impl<'i, R: Read> Scanner<'i, R> {
+    fn new(
+        reader: R,
+        instructions: &'i [&'i dyn Instruction],
+        grammar: &'i Grammar,
+        chunk_size: usize,
+    ) -> Self {
+        assert!(chunk_size > 0);
+        assert!((1..=19).contains(&grammar.max_digits));
+        Self {
+            reader,
+            instructions,
+            grammar,
+            chunk_size,
+            buffer: Vec::with_capacity(chunk_size * 2),
+            position: Position::default(),
+            tokens: VecDeque::new(),
+            eof: false,
+        }
+    }
+
+    /// Read the next chunk and lex everything that can no longer be cut off
+    fn fill(&mut self) -> io::Result<()> {
+        let mut chunk = vec![0; self.chunk_size];
+        let read = loop {
+            match self.reader.read(&mut chunk) {
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                result => break result?,
+            }
+        };
+        self.buffer.extend_from_slice(&chunk[..read]);
+        self.eof = read == 0;
+
+        let mut lexer =
+            Lexer::partial(&self.buffer, self.instructions, self.grammar, self.position);
+        if self.eof {
+            lexer.end = self.buffer.len();
+        }
+        self.tokens.extend(lexer.by_ref());
+        let (consumed, position) = (lexer.pos, lexer.position);
+        self.buffer.drain(..consumed);
+        self.position = position;
+        Ok(())
+    }
+}
+
+impl<'i, R: Read> Iterator for Scanner<'i, R> {
+    type Item = io::Result<Token<'i>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(token) = self.tokens.pop_front() {
+                return Some(Ok(token));
+            }
+            if self.eof {
+                return None;
+            }
+            if let Err(err) = self.fill() {
+                return Some(Err(err));
+            }
+        }
+    }
+}
+
+/// Error while running a memory dump
+#[derive(Debug)]
+enum Error {
+    Io(io::Error),
+    /// The instruction at `position` overflowed the accumulator
+    Overflow {
+        position: Position,
+    },
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "could not read memory: {err}"),
+            Error::Overflow { position } => write!(
+                f,
+                "accumulator overflowed at {}:{} (offset {})",
+                position.line, position.column, position.offset
+            ),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl Token<'_> {
+    /// Execute the instruction, near-misses are ignored
+    fn execute(&self, machine: &mut Machine) -> Result<(), Error> {
+        if let Ok(operands) = &self.operands {
+            self.instruction
+                .execute(machine, operands)
+                .map_err(|Overflow| Error::Overflow {
+                    position: self.position,
+                })?;
+        }
+        Ok(())
+    }
+}
+
+/// Execute every instruction found in a memory stream, reading `chunk_size` bytes at a time
+fn run_stream(
+    memory: impl Read,
+    instructions: &[&dyn Instruction],
+    grammar: &Grammar,
+    chunk_size: usize,
+) -> Result<Machine, Error> {
+    let mut machine = Machine::default();
+    for token in Scanner::new(memory, instructions, grammar, chunk_size) {
+        token?.execute(&mut machine)?;
+    }
+    Ok(machine)
+}
+
+/// Output format of [trace]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TraceFormat {
+    Table,
+    Json,
+}
+
+/// Escape `text` as a JSON string
+fn json_string(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len() + 2);
+    escaped.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// Execute every instruction, writing each instruction and near-miss to `out`
+///
+/// For each instruction it lists whether it was enabled and what it added to the total.
+fn trace(
+    memory: impl Read,
+    instructions: &[&dyn Instruction],
+    grammar: &Grammar,
+    format: TraceFormat,
+    out: &mut impl Write,
+) -> Result<Machine, Error> {
+    let mut machine = Machine::default();
+    match format {
+        TraceFormat::Table => writeln!(
+            out,
+            "{:>8} {:>10} {:<16} {:<8} {:>12} note",
+            "offset", "line:col", "text", "enabled", "contribution"
+        )?,
+        TraceFormat::Json => write!(out, "[")?,
+    }
+    let scanner = Scanner::new(memory, instructions, grammar, CHUNK_SIZE);
+    for (idx, token) in scanner.enumerate() {
+        let token = token?;
+        let enabled = machine.enabled;
+        let acc = machine.acc;
+        token.execute(&mut machine)?;
+        let contribution = machine.acc - acc;
+        let Position {
+            offset,
+            line,
+            column,
+        } = token.position;
+
+        match format {
+            TraceFormat::Table => {
+                let note = match &token.operands {
+                    Ok(operands) => format!("{} {operands:?}", token.instruction.name()),
+                    Err(rejection) => format!("rejected: {rejection}"),
+                };
+                writeln!(
+                    out,
+                    "{offset:>8} {:>10} {:<16} {enabled:<8} {contribution:>12} {note}",
+                    format!("{line}:{column}"),
+                    format!("{:?}", token.text),
+                )?
+            }
+            TraceFormat::Json => {
+                let separator = if idx == 0 { "" } else { "," };
+                let result = match &token.operands {
+                    Ok(operands) => format!("\"operands\":{operands:?}"),
+                    Err(rejection) => {
+                        format!("\"rejected\":{}", json_string(&rejection.to_string()))
+                    }
+                };
+                write!(
+                    out,
+                    "{separator}\n  {{\"offset\":{offset},\"line\":{line},\"column\":{column},\
+                    \"text\":{},\"instruction\":\"{}\",{result},\
+                    \"enabled\":{enabled},\"contribution\":{contribution}}}",
+                    json_string(&token.text),
+                    token.instruction.name(),
+                )?
+            }
+        }
+    }
+    if format == TraceFormat::Json {
+        writeln!(out, "\n]")?;
+    }
+    Ok(machine)
 }
 
 fn main() {
-    let data = read_input();
-    println!("{}", puzzle_1(&data));
-    println!("{}", puzzle_2(&data));
+    let format = std::env::args().find_map(|arg| match arg.as_str() {
+        "--trace" => Some(TraceFormat::Table),
+        "--trace=json" => Some(TraceFormat::Json),
+        _ => None,
+    });
+    if let Some(format) = format {
+        let instructions: [&dyn Instruction; 3] = [&Mul, &Do, &Dont];
+        trace(
+            read_input(),
+            &instructions,
+            &Grammar::default(),
+            format,
+            &mut io::stdout().lock(),
+        )
+        .unwrap_or_else(|err| panic!("{err}"));
+    }
+    println!("{}", puzzle_1(read_input()));
+    println!("{}", puzzle_2(read_input()));
 }
 
 #[cfg(test)]
 mod test {
-    use crate::{find_maybe_mul, find_mul};
+    use crate::{
+        find_maybe_mul, find_mul, run_stream, trace, Do, Dont, Error, Grammar, Instruction, Lexer,
+        Machine, Mul, Position, Rejection, Scanner, TraceFormat,
+    };
 
     #[test]
     fn test_puzzle_1() {
         let input = "xmul(2,4)%&mul[3,7]!@^do_not_mul(5,5)+mul(32,64]then(mul(11,8)mul(8,5))";
-        assert_eq!(find_mul(input), 161);
+        assert_eq!(find_mul(input.as_bytes()), 161);
     }
     #[test]
     fn test_puzzle_2() {
         let input = "xmul(2,4)&mul[3,7]!^don't()_mul(5,5)+mul(32,64](mul(11,8)undo()?mul(8,5))
 xmul(2,4)&mul[3,7]!^don't()_mul(5,5)+mul(32,64](mul(11,8)undo()?mul(8,5))
 ";
-        assert_eq!(find_maybe_mul(input), 96);
+        assert_eq!(find_maybe_mul(input.as_bytes()), 96);
+    }
+
+    #[test]
+    fn test_lexer() {
+        let instructions: [&dyn Instruction; 3] = [&Mul, &Do, &Dont];
+        let tokens: Vec<_> = Lexer::new(
+            b"mul(1,2)do()mul(1234,5)don't()mul(4,)mul(7,8)",
+            &instructions,
+            &Grammar::default(),
+        )
+        .filter_map(|token| Some((token.instruction.name(), token.operands.ok()?)))
+        .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                ("mul", vec![1, 2]),
+                ("do", vec![]),
+                ("don't", vec![]),
+                ("mul", vec![7, 8]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_run_stream() {
+        let input = "xmul(2,4)&mul[3,7]!^don't()_mul(5,5)+mul(32,64](mul(11,8)undo()?mul(8,5))
+xmul(2,4)&mul[3,7]!^don't()_mul(5,5)+mul(32,64](mul(11,8)undo()?mul(8,5))
+";
+        for chunk_size in 1..=input.len() + 1 {
+            let machine = run_stream(
+                input.as_bytes(),
+                &[&Mul, &Do, &Dont],
+                &Grammar::default(),
+                chunk_size,
+            )
+            .unwrap();
+            assert_eq!(machine.acc, 96, "chunk size {chunk_size}");
+            assert!(machine.enabled);
+        }
+    }
+
+    #[test]
+    fn test_near_miss() {
+        let instructions: [&dyn Instruction; 3] = [&Mul, &Do, &Dont];
+        let grammar = Grammar::default();
+        let input = b"mul[3,7]\nmul(32,64]do_don't(";
+        let tokens: Vec<_> = Lexer::new(input, &instructions, &grammar)
+            .map(|token| (token.position, token.text, token.operands))
+            .collect();
+        let unexpected = |expected, found| Err(Rejection::Unexpected { expected, found });
+        assert_eq!(
+            tokens,
+            vec![
+                (
+                    Position {
+                        offset: 0,
+                        line: 1,
+                        column: 1
+                    },
+                    "mul[".to_string(),
+                    unexpected("'('", Some(b'['))
+                ),
+                (
+                    Position {
+                        offset: 9,
+                        line: 2,
+                        column: 1
+                    },
+                    "mul(32,64]".to_string(),
+                    unexpected("')'", Some(b']'))
+                ),
+                (
+                    Position {
+                        offset: 19,
+                        line: 2,
+                        column: 11
+                    },
+                    "do_".to_string(),
+                    unexpected("'('", Some(b'_'))
+                ),
+                (
+                    Position {
+                        offset: 22,
+                        line: 2,
+                        column: 14
+                    },
+                    "don't(".to_string(),
+                    unexpected("')'", None)
+                ),
+            ]
+        );
+
+        // Chunk boundaries don't change what is found, or where
+        let expected: Vec<_> = Lexer::new(input, &instructions, &grammar)
+            .map(|token| (token.position, token.text))
+            .collect();
+        for chunk_size in 1..=input.len() {
+            let tokens: Vec<_> = Scanner::new(&input[..], &instructions, &grammar, chunk_size)
+                .map(|token| token.map(|token| (token.position, token.text)).unwrap())
+                .collect();
+            assert_eq!(tokens, expected, "chunk size {chunk_size}");
+        }
+    }
+
+    #[test]
+    fn test_trace() {
+        let mut out = vec![];
+        let machine = trace(
+            &b"mul(2,4)don't()mul(3,3)"[..],
+            &[&Mul, &Do, &Dont],
+            &Grammar::default(),
+            TraceFormat::Json,
+            &mut out,
+        )
+        .unwrap();
+        assert_eq!(machine.acc, 8);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            r#"[
+  {"offset":0,"line":1,"column":1,"text":"mul(2,4)","instruction":"mul","operands":[2, 4],"enabled":true,"contribution":8},
+  {"offset":8,"line":1,"column":9,"text":"don't()","instruction":"don't","operands":[],"enabled":true,"contribution":0},
+  {"offset":15,"line":1,"column":16,"text":"mul(3,3)","instruction":"mul","operands":[3, 3],"enabled":false,"contribution":0}
+]
+"#
+        );
+    }
+
+    #[test]
+    fn test_grammar() {
+        let grammar = Grammar {
+            max_digits: 5,
+            separators: vec![b',', b';'],
+            whitespace: 1,
+        };
+        let input = "mul(12345,2)mul( 3 ; 4 )mul(123456,1)mul(1,  2)mul(1:2)";
+        for chunk_size in 1..=input.len() {
+            let machine = run_stream(input.as_bytes(), &[&Mul], &grammar, chunk_size).unwrap();
+            assert_eq!(machine.acc, 12345 * 2 + 3 * 4, "chunk size {chunk_size}");
+        }
+    }
+
+    #[test]
+    fn test_overflow() {
+        let grammar = Grammar {
+            max_digits: 19,
+            ..Grammar::default()
+        };
+        // Three products fit a u128, the fourth overflows
+        let max = 10u64.pow(19) - 1;
+        let input = format!("mul({max},{max})mul({max},{max})\nmul({max},{max})mul({max},{max})");
+        let result = run_stream(input.as_bytes(), &[&Mul], &grammar, 16);
+        let Err(Error::Overflow { position }) = result else {
+            panic!("expected overflow, got {result:?}");
+        };
+        assert_eq!((position.line, position.column), (2, 45));
+
+        let machine = run_stream(&b"mul(999,999)"[..], &[&Mul], &Grammar::default(), 16).unwrap();
+        assert_eq!(
+            machine,
+            Machine {
+                enabled: true,
+                acc: 998001
+            }
+        );
     }
 }
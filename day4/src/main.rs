@@ -1,164 +1,386 @@
-use std::{collections::HashSet, fs};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    fs,
+};
 
-fn read_input() -> Vec<Vec<char>> {
+use unicode_segmentation::UnicodeSegmentation;
+
+fn read_input() -> Grid {
     let data = fs::read_to_string("input.txt").unwrap();
     mangle(&data)
 }
 
-fn mangle(input: &str) -> Vec<Vec<char>> {
-    input
-        .lines()
-        .filter(|l| !l.is_empty())
-        .map(|f| f.chars().collect())
-        .collect()
+fn mangle(input: &str) -> Grid {
+    Grid {
+        rows: input
+            .lines()
+            .filter(|l| !l.is_empty())
+            .map(letters)
+            .collect(),
+        ignore_case: false,
+    }
+}
+
+/// Split text into letters, a letter is a grapheme so `é` is one letter even if it is two chars
+fn letters(text: &str) -> Vec<String> {
+    text.graphemes(true).map(String::from).collect()
+}
+
+/// Grid of letters, rows can have different lengths
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Grid {
+    rows: Vec<Vec<String>>,
+    /// Compare letters case-insensitively
+    ignore_case: bool,
 }
 
-fn puzzle_1(input: &[Vec<char>]) -> usize {
-    let word = "XMAS".chars().collect::<Vec<char>>();
-    let column_length = input.len() - word.len();
-    let row_length = input[0].len() - word.len();
+impl Grid {
+    /// Letter at (row, column), `None` outside the grid or past the end of a short row
+    fn get(&self, row: usize, column: usize) -> Option<&str> {
+        self.rows.get(row)?.get(column).map(String::as_str)
+    }
+
+    /// (row, column) of every letter in the grid
+    fn positions(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.rows
+            .iter()
+            .enumerate()
+            .flat_map(|(row, line)| (0..line.len()).map(move |column| (row, column)))
+    }
 
-    let mut hits = HashSet::new();
-    for row in 0..=column_length {
-        for column in 0..=row_length {
-            search_window(input, row, column, &word, &mut hits);
+    /// The form letters are compared in
+    fn key<'a>(&self, letter: &'a str) -> Cow<'a, str> {
+        match self.ignore_case {
+            true => Cow::Owned(letter.to_lowercase()),
+            false => Cow::Borrowed(letter),
         }
     }
-    hits.len()
 }
 
-fn puzzle_2(input: &[Vec<char>]) -> usize {
-    let word = "MAS".chars().collect::<Vec<char>>();
-    let mut word_reversed = word.clone();
-    word_reversed.reverse();
-    let column_length = input.len() - word.len();
-    let row_length = input[0].len() - word.len();
+fn puzzle_1(input: &Grid) -> usize {
+    search(input, &["XMAS"]).len()
+}
 
-    let mut hits = 0;
-    for row in 0..=column_length {
-        for column in 0..=row_length {
-            let [left_2_right, right_2_left] = diagonals(input, word.len(), row, column);
-            let l2r = (left_2_right == word) || (left_2_right == word_reversed);
-            let r2l = (right_2_left == word) || (right_2_left == word_reversed);
-            if l2r && r2l {
-                hits += 1;
-            }
+fn puzzle_2(input: &Grid) -> usize {
+    let x_mas = Pattern::parse(
+        r#"
+M.S
+.A.
+M.S
+"#,
+    );
+    find_pattern(input, &x_mas.variants()).len()
+}
+
+/// One of the eight directions a word can be written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Direction {
+    Right,
+    DownRight,
+    Down,
+    DownLeft,
+    Left,
+    UpLeft,
+    Up,
+    UpRight,
+}
+
+impl Direction {
+    const ALL: [Direction; 8] = [
+        Direction::Right,
+        Direction::DownRight,
+        Direction::Down,
+        Direction::DownLeft,
+        Direction::Left,
+        Direction::UpLeft,
+        Direction::Up,
+        Direction::UpRight,
+    ];
+
+    /// (row, column) step
+    fn step(&self) -> (isize, isize) {
+        match self {
+            Direction::Right => (0, 1),
+            Direction::DownRight => (1, 1),
+            Direction::Down => (1, 0),
+            Direction::DownLeft => (1, -1),
+            Direction::Left => (0, -1),
+            Direction::UpLeft => (-1, -1),
+            Direction::Up => (-1, 0),
+            Direction::UpRight => (-1, 1),
         }
     }
-    hits
 }
 
-/// Search the window for all possible positions of word
-fn search_window(
-    window: &[Vec<char>],
-    height_offset: usize,
-    width_offset: usize,
-    word: &[char],
-    hits: &mut HashSet<((usize, usize), (usize, usize))>,
-) {
-    // Check window height >= word length
-    assert!(window.len() >= height_offset + word.len());
-
-    // horizontal hits
-    for (row_idx, line) in window[height_offset..].iter().enumerate().take(word.len()) {
-        // Check window width >= word length
-        assert!(
-            line.len() >= width_offset + word.len(),
-            "{line:?}, offset: {width_offset}, word: {word:?}"
-        );
-        let line_start = (height_offset + row_idx, width_offset);
-        let line_end = (height_offset + row_idx, width_offset + word.len() - 1);
-        let mut subline = line[width_offset..width_offset + word.len()].to_vec();
-        if subline == *word {
-            hits.insert((line_start, line_end));
-        }
-        subline.reverse();
-        if subline == *word {
-            hits.insert((line_start, line_end));
+/// Word found in the grid, `start` and `end` are the (row, column) of the first and last letter
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Match<'a> {
+    word: &'a str,
+    start: (usize, usize),
+    end: (usize, usize),
+    direction: Direction,
+}
+
+impl Match<'_> {
+    /// (row, column) of every letter of the word
+    fn cells(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let (row_step, column_step) = self.direction.step();
+        (0..self.word.graphemes(true).count() as isize).map(move |idx| {
+            (
+                self.start.0.wrapping_add_signed(idx * row_step),
+                self.start.1.wrapping_add_signed(idx * column_step),
+            )
+        })
+    }
+}
+
+/// How [render] marks the letters of matched words
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Highlight {
+    /// Matched letters in bold red
+    Ansi,
+    /// Letters not part of a match replaced by `.`
+    Dotted,
+}
+
+/// Render the grid with the letters of every match highlighted
+fn render(grid: &Grid, hits: &[Match], highlight: Highlight) -> String {
+    let matched: HashSet<(usize, usize)> = hits.iter().flat_map(|hit| hit.cells()).collect();
+    let mut output = String::new();
+    for (row, line) in grid.rows.iter().enumerate() {
+        for (column, letter) in line.iter().enumerate() {
+            match (highlight, matched.contains(&(row, column))) {
+                (Highlight::Ansi, true) => output.push_str(&format!("\x1b[1;31m{letter}\x1b[0m")),
+                (Highlight::Dotted, false) => output.push('.'),
+                _ => output.push_str(letter),
+            }
         }
+        output.push('\n');
     }
+    output
+}
+
+/// List every match as `word (row, column) -> (row, column) direction`
+fn render_matches(hits: &[Match]) -> String {
+    hits.iter()
+        .map(|hit| {
+            format!(
+                "{} {:?} -> {:?} {:?}\n",
+                hit.word, hit.start, hit.end, hit.direction
+            )
+        })
+        .collect()
+}
 
-    // vertical hits
-    for column in 0..word.len() {
-        let line_start = (height_offset, width_offset + column);
-        let line_end = (height_offset + word.len() - 1, width_offset + column);
+/// Prefix tree of the words to search for, node 0 is the root
+struct Trie<'a> {
+    nodes: Vec<TrieNode<'a>>,
+}
 
-        let mut subline = Vec::new();
-        for line in window[height_offset..].iter().take(word.len()) {
-            subline.push(line[width_offset + column]);
+#[derive(Default)]
+struct TrieNode<'a> {
+    children: HashMap<String, usize>,
+    /// Word ending at this node
+    word: Option<&'a str>,
+}
+
+impl<'a> Trie<'a> {
+    /// Build the trie from the letters of each word, compared as they are in `grid`
+    fn new(words: &[&'a str], grid: &Grid) -> Self {
+        let mut nodes = vec![TrieNode::default()];
+        for word in words {
+            let mut node = 0;
+            for letter in word.graphemes(true) {
+                let letter = grid.key(letter);
+                node = match nodes[node].children.get(letter.as_ref()) {
+                    Some(child) => *child,
+                    None => {
+                        nodes.push(TrieNode::default());
+                        let child = nodes.len() - 1;
+                        nodes[node].children.insert(letter.into_owned(), child);
+                        child
+                    }
+                };
+            }
+            nodes[node].word = Some(word);
         }
-        if subline == word {
-            hits.insert((line_start, line_end));
+        Self { nodes }
+    }
+}
+
+/// Find all words in the grid, in all eight directions
+///
+/// Every cell and direction is walked once, following the trie for as long
+/// as the letters form the prefix of a word.
+fn search<'a>(grid: &Grid, words: &[&'a str]) -> Vec<Match<'a>> {
+    let trie = Trie::new(words, grid);
+    let mut hits = Vec::new();
+    for (row, column) in grid.positions() {
+        for direction in Direction::ALL {
+            let (row_step, column_step) = direction.step();
+            let mut node = 0;
+            let mut position = (row, column);
+            while let Some(letter) = grid.get(position.0, position.1) {
+                let Some(child) = trie.nodes[node].children.get(grid.key(letter).as_ref()) else {
+                    break;
+                };
+                node = *child;
+                // Single letter words have no direction, only report them once
+                let single_letter = position == (row, column);
+                if let Some(word) = trie.nodes[node]
+                    .word
+                    .filter(|_| !single_letter || direction == Direction::ALL[0])
+                {
+                    hits.push(Match {
+                        word,
+                        start: (row, column),
+                        end: position,
+                        direction,
+                    });
+                }
+                let (Some(next_row), Some(next_column)) = (
+                    position.0.checked_add_signed(row_step),
+                    position.1.checked_add_signed(column_step),
+                ) else {
+                    break;
+                };
+                position = (next_row, next_column);
+            }
         }
-        subline.reverse();
-        if subline == word {
-            hits.insert((line_start, line_end));
+    }
+    hits
+}
+
+/// Small 2D template of letters, `None` cells match any letter
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Pattern {
+    cells: Vec<Vec<Option<String>>>,
+}
+
+impl Pattern {
+    /// Parse a template, `.` is a wildcard and short rows are padded with wildcards
+    fn parse(template: &str) -> Self {
+        let mut cells: Vec<Vec<Option<String>>> = template
+            .lines()
+            .filter(|l| !l.is_empty())
+            .map(|l| {
+                letters(l)
+                    .into_iter()
+                    .map(|letter| (letter != ".").then_some(letter))
+                    .collect()
+            })
+            .collect();
+        let width = cells.iter().map(|row| row.len()).max().unwrap_or(0);
+        for row in cells.iter_mut() {
+            row.resize(width, None);
         }
+        Self { cells }
     }
 
-    // diagonal hits
-    let [mut left_2_right, mut right_2_left] =
-        diagonals(window, word.len(), height_offset, width_offset);
-    if left_2_right == word {
-        hits.insert((
-            (height_offset, width_offset),
-            (
-                height_offset + word.len() - 1,
-                width_offset + word.len() - 1,
-            ),
-        ));
-    }
-    if right_2_left == word {
-        hits.insert((
-            (height_offset, width_offset + word.len() - 1),
-            (height_offset + word.len() - 1, width_offset),
-        ));
-    }
-    left_2_right.reverse();
-    right_2_left.reverse();
-    if left_2_right == word {
-        hits.insert((
-            (height_offset, width_offset),
-            (
-                height_offset + word.len() - 1,
-                width_offset + word.len() - 1,
-            ),
-        ));
+    fn height(&self) -> usize {
+        self.cells.len()
+    }
+
+    fn width(&self) -> usize {
+        self.cells.first().map_or(0, |row| row.len())
+    }
+
+    /// Rotate 90 degrees clockwise
+    fn rotate(&self) -> Self {
+        let cells = (0..self.width())
+            .map(|column| {
+                (0..self.height())
+                    .rev()
+                    .map(|row| self.cells[row][column].clone())
+                    .collect()
+            })
+            .collect();
+        Self { cells }
+    }
+
+    /// Mirror left to right
+    fn reflect(&self) -> Self {
+        let cells = self
+            .cells
+            .iter()
+            .map(|row| row.iter().rev().cloned().collect())
+            .collect();
+        Self { cells }
     }
-    if right_2_left == word {
-        hits.insert((
-            (height_offset, width_offset + word.len() - 1),
-            (height_offset + word.len() - 1, width_offset),
-        ));
+
+    /// All distinct rotations and reflections of the pattern, starting with the pattern itself
+    fn variants(&self) -> Vec<Pattern> {
+        let mut variants: Vec<Pattern> = Vec::new();
+        for mut variant in [self.clone(), self.reflect()] {
+            for _ in 0..4 {
+                let next = variant.rotate();
+                if !variants.contains(&variant) {
+                    variants.push(variant);
+                }
+                variant = next;
+            }
+        }
+        variants
     }
+
+    /// Does the pattern match with its top left cell at (row, column)
+    fn matches_at(&self, grid: &Grid, row: usize, column: usize) -> bool {
+        self.cells.iter().enumerate().all(|(row_idx, cells)| {
+            cells.iter().enumerate().all(|(column_idx, cell)| {
+                match (cell, grid.get(row + row_idx, column + column_idx)) {
+                    (None, Some(_)) => true,
+                    (Some(cell), Some(letter)) => grid.key(cell) == grid.key(letter),
+                    (_, None) => false,
+                }
+            })
+        })
+    }
+}
+
+/// Occurrence of a pattern, `position` is the (row, column) of its top left cell
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct PatternMatch<'a> {
+    pattern: &'a Pattern,
+    position: (usize, usize),
 }
 
-/// Returns the diagonal chars in the window
-fn diagonals(
-    window: &[Vec<char>],
-    size: usize,
-    height_offset: usize,
-    width_offset: usize,
-) -> [Vec<char>; 2] {
-    let mut left_2_right = Vec::new();
-    let mut right_2_left = Vec::new();
-    for (idx, line) in window[height_offset..].iter().enumerate().take(size) {
-        left_2_right.push(line[width_offset..][idx]);
-        right_2_left.push(line[width_offset..][size - 1 - idx]);
+/// Find every occurrence of every pattern in the grid
+///
+/// To match under rotations and reflections pass [Pattern::variants].
+fn find_pattern<'a>(grid: &Grid, patterns: &'a [Pattern]) -> Vec<PatternMatch<'a>> {
+    let mut hits = Vec::new();
+    for (row, column) in grid.positions() {
+        for pattern in patterns {
+            if pattern.matches_at(grid, row, column) {
+                hits.push(PatternMatch {
+                    pattern,
+                    position: (row, column),
+                });
+            }
+        }
     }
-    [left_2_right, right_2_left]
+    hits
 }
 
 fn main() {
     let data = read_input();
+    let highlight = std::env::args().find_map(|arg| match arg.as_str() {
+        "--render" => Some(Highlight::Dotted),
+        "--render=ansi" => Some(Highlight::Ansi),
+        _ => None,
+    });
+    if let Some(highlight) = highlight {
+        let hits = search(&data, &["XMAS"]);
+        print!("{}", render(&data, &hits, highlight));
+        print!("{}", render_matches(&hits));
+    }
     println!("{}", puzzle_1(&data));
     println!("{}", puzzle_2(&data));
 }
 
 #[cfg(test)]
 mod test {
-    use std::collections::HashSet;
-
     #[test]
     fn test_puzzle_1() {
         let input = crate::mangle(
@@ -197,7 +419,9 @@ MXMXAXMASX
     }
 
     #[test]
-    fn test_diagonals() {
+    fn test_pattern() {
+        use crate::{find_pattern, Pattern};
+
         let input = crate::mangle(
             r#"
 MMMSXXMASM
@@ -212,16 +436,108 @@ MAMMMXMMMM
 MXMXAXMASX
 "#,
         );
-        let result = crate::diagonals(&input, 4, 1, 0);
-        assert_eq!(result, [vec!['M', 'S', 'X', 'M'], vec!['S', 'A', 'M', 'M']]);
-        let result = crate::diagonals(&input, 4, 3, 0);
-        assert_eq!(result, [vec!['A', 'S', 'A', 'M'], vec!['S', 'A', 'M', 'X']]);
-        let result = crate::diagonals(&input, 4, 5, 2);
-        assert_eq!(result, [vec!['A', 'M', 'S', 'A'], vec!['M', 'M', 'M', 'X']]);
+
+        let x_mas = Pattern::parse("M.S\n.A.\nM.S");
+        assert_eq!(x_mas.variants().len(), 4);
+        assert_eq!(x_mas.rotate(), Pattern::parse("M.M\n.A.\nS.S"));
+        let variants = x_mas.variants();
+        let hits = find_pattern(&input, &variants);
+        assert_eq!(hits.len(), 9);
+        assert!(hits.iter().any(|hit| hit.position == (0, 1)));
+        assert_eq!(find_pattern(&input, &[x_mas]).len(), 2);
+
+        // Part 1 as data, a line and a diagonal cover all eight directions
+        let xmas = Pattern::parse("XMAS").variants();
+        let diagonal = Pattern::parse("X\n.M\n..A\n...S").variants();
+        assert_eq!(xmas.len() + diagonal.len(), 8);
+        let patterns = [xmas, diagonal].concat();
+        assert_eq!(find_pattern(&input, &patterns).len(), 18);
+
+        // Plus-signs with a wildcard center
+        let plus = [Pattern::parse(".A.\nA.A\n.A.")];
+        assert_eq!(plus[0].variants().len(), 1);
+        let input = crate::mangle(".A..\nAAA.\n.AA.\n.AAA");
+        let hits = find_pattern(&input, &plus);
+        assert_eq!(
+            hits.iter().map(|hit| hit.position).collect::<Vec<_>>(),
+            [(0, 0)]
+        );
+    }
+
+    #[test]
+    fn test_search() {
+        use crate::{Direction, Match};
+
+        let input = crate::mangle(
+            r#"
+..X...
+.SAMX.
+.A..A.
+XMAS.S
+.X....
+"#,
+        );
+        let mut hits = crate::search(&input, &["XMAS", "XMA", "SAX"]);
+        hits.sort_by_key(|hit| (hit.word, hit.start, hit.end));
+        assert_eq!(
+            hits,
+            vec![
+                Match {
+                    word: "XMA",
+                    start: (0, 2),
+                    end: (2, 4),
+                    direction: Direction::DownRight
+                },
+                Match {
+                    word: "XMA",
+                    start: (1, 4),
+                    end: (1, 2),
+                    direction: Direction::Left
+                },
+                Match {
+                    word: "XMA",
+                    start: (3, 0),
+                    end: (3, 2),
+                    direction: Direction::Right
+                },
+                Match {
+                    word: "XMA",
+                    start: (4, 1),
+                    end: (2, 1),
+                    direction: Direction::Up
+                },
+                Match {
+                    word: "XMAS",
+                    start: (0, 2),
+                    end: (3, 5),
+                    direction: Direction::DownRight
+                },
+                Match {
+                    word: "XMAS",
+                    start: (1, 4),
+                    end: (1, 1),
+                    direction: Direction::Left
+                },
+                Match {
+                    word: "XMAS",
+                    start: (3, 0),
+                    end: (3, 3),
+                    direction: Direction::Right
+                },
+                Match {
+                    word: "XMAS",
+                    start: (4, 1),
+                    end: (1, 1),
+                    direction: Direction::Up
+                },
+            ]
+        );
     }
 
     #[test]
-    fn test_search_window() {
+    fn test_render() {
+        use crate::{render, render_matches, search, Highlight};
+
         let input = crate::mangle(
             r#"
 MMMSXXMASM
@@ -236,15 +552,67 @@ MAMMMXMMMM
 MXMXAXMASX
 "#,
         );
-        let word: Vec<char> = "XMAS".to_string().chars().collect();
-        let mut hits = HashSet::new();
-        crate::search_window(&input, 1, 0, &word, &mut hits);
-        assert_eq!(hits.len(), 0);
-        let mut hits = HashSet::new();
-        crate::search_window(&input, 1, 5, &word, &mut hits);
-        assert_eq!(hits.len(), 1);
-        let mut hits = HashSet::new();
-        crate::search_window(&input, 7, 2, &word, &mut hits);
-        assert_eq!(hits.len(), 1);
+        let hits = search(&input, &["XMAS"]);
+        assert_eq!(
+            render(&input, &hits, Highlight::Dotted),
+            r#"....XXMAS.
+.SAMXMS...
+...S..A...
+..A.A.MS.X
+XMASAMX.MM
+X.....XA.A
+S.S.S.S.SS
+.A.A.A.A.A
+..M.M.M.MM
+.X.X.XMASX
+"#
+        );
+
+        let hits = search(&crate::mangle("XMAS\nM..."), &["XM"]);
+        assert_eq!(
+            render(&crate::mangle("XMAS\nM..."), &hits, Highlight::Ansi),
+            "\x1b[1;31mX\x1b[0m\x1b[1;31mM\x1b[0mAS\n\x1b[1;31mM\x1b[0m...\n"
+        );
+        assert_eq!(
+            render_matches(&hits),
+            "XM (0, 0) -> (0, 1) Right\nXM (0, 0) -> (1, 0) Down\n"
+        );
+    }
+
+    #[test]
+    fn test_irregular_grid() {
+        use crate::{find_pattern, search, Grid, Pattern};
+
+        // Ragged rows, cells past the end of a short row are never matched
+        let input = crate::mangle("XMAS\nM\nAMX\nS...X");
+        let mut hits: Vec<_> = search(&input, &["XMAS"])
+            .iter()
+            .map(|hit| (hit.start, hit.end))
+            .collect();
+        hits.sort();
+        assert_eq!(hits, [((0, 0), (0, 3)), ((0, 0), (3, 0))]);
+        let column = [Pattern::parse("X\nM\nA\nS")];
+        assert_eq!(find_pattern(&input, &column).len(), 1);
+        // A wildcard still needs a letter to match
+        let corner = [Pattern::parse("XM\nM.")];
+        assert_eq!(find_pattern(&input, &corner).len(), 0);
+
+        // Letters made up of multiple chars
+        let input = crate::mangle("ce\u{301}\ne\u{301}e\u{301}\nçé");
+        assert_eq!(input.get(1, 1), Some("e\u{301}"));
+        assert_eq!(input.get(1, 2), None);
+        let hits = search(&input, &["ce\u{301}", "é"]);
+        // "cé" right, down and diagonally, the composed "é" only matches itself
+        assert_eq!(hits.len(), 4);
+
+        // Case-insensitive
+        let input = Grid {
+            ignore_case: true,
+            ..crate::mangle("xMaS\nÉ")
+        };
+        assert_eq!(search(&input, &["XMAS", "é"]).len(), 2);
+        assert_eq!(search(&crate::mangle("xMaS"), &["XMAS"]).len(), 0);
+        let pattern = [Pattern::parse("XmA")];
+        assert_eq!(find_pattern(&input, &pattern).len(), 1);
     }
 }
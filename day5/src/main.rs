@@ -1,8 +1,19 @@
-use std::{collections::HashMap, fs};
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BTreeSet, BinaryHeap, HashMap, HashSet, VecDeque},
+    fmt::Display,
+    fs,
+};
 
 #[derive(Debug, PartialEq, Eq)]
 struct Rule(u32, u32);
 
+impl Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}|{}", self.0, self.1)
+    }
+}
+
 fn read_input() -> (Vec<Rule>, Vec<Vec<u32>>) {
     let data = fs::read_to_string("input.txt").unwrap();
     mangle(&data)
@@ -43,67 +54,427 @@ fn mangle(input: &str) -> (Vec<Rule>, Vec<Vec<u32>>) {
     (rules, updates)
 }
 
-fn update_valid(rules: &[Rule], update: &[u32]) -> bool {
-    let mut to_test: HashMap<u32, usize> = HashMap::new();
-    for (idx, page) in update.iter().enumerate() {
-        to_test.insert(*page, idx);
-    }
-    for rule in rules {
-        match (to_test.get(&rule.0), to_test.get(&rule.1)) {
-            (Some(left), Some(right)) if left >= right => return false,
-            (Some(left), Some(right)) if left == right => panic!("Same page twice in rule"),
-            _ => {}
-        }
-    }
-
-    true
-}
-
-fn correct_order(rules: &[Rule], update: &[u32]) -> Vec<u32> {
-    let mut sorted = Vec::new();
-
-    for page in update {
-        // Loop through all the sorted values
-        let mut insertion_idx = 0;
-        let mut break_sort = false;
-        for (idx, sorted_value) in sorted.iter().enumerate() {
-            for rule in rules {
-                match rule {
-                    // page should be inserted before current location
-                    Rule(left, right) if left == page && right == sorted_value => {
-                        // update the insertion index
-                        insertion_idx = idx;
-                        // break the sorted loop
-                        break_sort = true;
-                        // break the rule loop
-                        break;
-                    }
-                    // page should be inserted after current location
-                    Rule(left, right) if right == page && left == sorted_value => {
-                        // set index at least after this point, once we hit a "before" rule or the
-                        // end of the sorted list we know the insertion index
-                        insertion_idx = idx + 1;
-                        // break the rule loop
-                        break;
-                    }
-                    // rule does not apply
-                    _ => {}
+/// The rules between the pages of one update, as a graph of indices into the update
+struct PrecedenceGraph<'a> {
+    pages: &'a [u32],
+    /// `after[a]` are the pages that must come after page `a`
+    after: Vec<Vec<usize>>,
+    /// `before[b]` are the pages that must come before page `b`
+    before: Vec<Vec<usize>>,
+}
+
+/// Rules that contradict each other, each page must come before the next and the last before the first
+#[derive(Debug, PartialEq, Eq)]
+struct Cycle(Vec<u32>);
+
+impl Display for Cycle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "rules form a cycle: ")?;
+        for page in &self.0 {
+            write!(f, "{page} -> ")?;
+        }
+        write!(f, "{}", self.0[0])
+    }
+}
+
+impl<'a> PrecedenceGraph<'a> {
+    /// Build the graph from the rules that apply to both of their pages in `pages`
+    fn new(rules: &[Rule], pages: &'a [u32]) -> Self {
+        let mut index: HashMap<u32, usize> = HashMap::new();
+        for (idx, page) in pages.iter().enumerate() {
+            if index.insert(*page, idx).is_some() {
+                panic!("Same page twice in update");
+            }
+        }
+        let mut after = vec![Vec::new(); pages.len()];
+        let mut before = vec![Vec::new(); pages.len()];
+        for rule in rules {
+            if let (Some(left), Some(right)) = (index.get(&rule.0), index.get(&rule.1)) {
+                after[*left].push(*right);
+                before[*right].push(*left);
+            }
+        }
+        Self {
+            pages,
+            after,
+            before,
+        }
+    }
+
+    /// Topological sort of the pages
+    ///
+    /// When several pages can go next the one earliest in the update goes first,
+    /// so an update that is already in order stays the same.
+    fn sort(&self) -> Result<Vec<u32>, Cycle> {
+        let mut in_degree: Vec<usize> = self.before.iter().map(|before| before.len()).collect();
+        let mut ready: BinaryHeap<Reverse<usize>> = (0..self.pages.len())
+            .filter(|idx| in_degree[*idx] == 0)
+            .map(Reverse)
+            .collect();
+
+        let mut sorted = Vec::with_capacity(self.pages.len());
+        while let Some(Reverse(idx)) = ready.pop() {
+            sorted.push(self.pages[idx]);
+            for right in &self.after[idx] {
+                in_degree[*right] -= 1;
+                if in_degree[*right] == 0 {
+                    ready.push(Reverse(*right));
                 }
             }
-            if break_sort {
-                break;
+        }
+
+        match in_degree.iter().position(|degree| *degree > 0) {
+            Some(idx) => Err(self.find_cycle(&in_degree, idx)),
+            None => Ok(sorted),
+        }
+    }
+
+    /// Find a cycle among the pages the sort could not place, starting the search at `start`
+    ///
+    /// Every unplaced page has an unplaced page before it, walking back along those
+    /// must eventually revisit a page.
+    fn find_cycle(&self, in_degree: &[usize], start: usize) -> Cycle {
+        let mut path = vec![start];
+        let mut visited: HashMap<usize, usize> = HashMap::from([(start, 0)]);
+        let mut current = start;
+        loop {
+            current = *self.before[current]
+                .iter()
+                .find(|left| in_degree[**left] > 0)
+                .expect("unplaced page has an unplaced page before it");
+            if let Some(cycle_start) = visited.get(&current) {
+                // path was walked backwards, reverse it to follow the rules
+                let mut cycle: Vec<u32> = path[*cycle_start..]
+                    .iter()
+                    .map(|idx| self.pages[*idx])
+                    .collect();
+                cycle.reverse();
+                return Cycle(cycle);
             }
+            visited.insert(current, path.len());
+            path.push(current);
         }
-        sorted.insert(insertion_idx, *page);
     }
+}
+
+fn correct_order(rules: &[Rule], update: &[u32]) -> Result<Vec<u32>, Cycle> {
+    PrecedenceGraph::new(rules, update).sort()
+}
+
+/// Rule broken by an update, `positions` are the indices of the rule's pages in the update
+#[derive(Debug, PartialEq, Eq)]
+struct Violation<'a> {
+    rule: &'a Rule,
+    positions: (usize, usize),
+}
+
+impl Display for Violation<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Rule(left, right) = self.rule;
+        let (left_idx, right_idx) = self.positions;
+        write!(
+            f,
+            "{} broken: {left} at {left_idx} comes after {right} at {right_idx}",
+            self.rule
+        )
+    }
+}
+
+/// Every rule the update breaks
+fn violations<'a>(rules: &'a [Rule], update: &[u32]) -> Vec<Violation<'a>> {
+    let index: HashMap<u32, usize> = update
+        .iter()
+        .enumerate()
+        .map(|(idx, page)| (*page, idx))
+        .collect();
+    rules
+        .iter()
+        .filter_map(|rule| {
+            let positions = (*index.get(&rule.0)?, *index.get(&rule.1)?);
+            (positions.0 > positions.1).then_some(Violation { rule, positions })
+        })
+        .collect()
+}
+
+/// Audit of a hand maintained rule set against the updates
+#[derive(Debug, PartialEq, Eq)]
+struct Audit<'a> {
+    /// Violated rules of each invalid update, by index of the update
+    invalid: Vec<(usize, Vec<Violation<'a>>)>,
+    /// Rules implied by other rules in every update that has both pages, or repeated
+    redundant: Vec<&'a Rule>,
+    /// Rules of which no update has both pages
+    unused: Vec<&'a Rule>,
+    /// Pages in updates that no rule mentions
+    uncovered: Vec<u32>,
+}
+
+impl Display for Audit<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (idx, violations) in &self.invalid {
+            writeln!(f, "update {idx} is invalid:")?;
+            for violation in violations {
+                writeln!(f, "    {violation}")?;
+            }
+        }
+        let list = |rules: &[&Rule]| {
+            rules
+                .iter()
+                .map(|rule| rule.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        writeln!(f, "redundant rules: {}", list(&self.redundant))?;
+        writeln!(f, "unused rules: {}", list(&self.unused))?;
+        writeln!(f, "pages without rules: {:?}", self.uncovered)
+    }
+}
+
+/// Is `rule` implied by a chain of at least two other rules between the pages of an update
+///
+/// Rules only apply when both pages are in the update, so the chain can't go through other pages.
+fn implied(rules: &[Rule], rule: &Rule, pages: &HashSet<u32>) -> bool {
+    let mut after: HashMap<u32, Vec<u32>> = HashMap::new();
+    for Rule(left, right) in rules.iter().filter(|other| *other != rule) {
+        if pages.contains(left) && pages.contains(right) {
+            after.entry(*left).or_default().push(*right);
+        }
+    }
+    let mut visited = HashSet::from([rule.0]);
+    let mut queue = VecDeque::from([rule.0]);
+    while let Some(page) = queue.pop_front() {
+        for next in after.get(&page).into_iter().flatten() {
+            if *next == rule.1 {
+                return true;
+            }
+            if visited.insert(*next) {
+                queue.push_back(*next);
+            }
+        }
+    }
+    false
+}
+
+fn audit<'a>(rules: &'a [Rule], updates: &[Vec<u32>]) -> Audit<'a> {
+    let invalid = updates
+        .iter()
+        .enumerate()
+        .map(|(idx, update)| (idx, violations(rules, update)))
+        .filter(|(_, violations)| !violations.is_empty())
+        .collect();
+
+    let update_pages: Vec<HashSet<u32>> = updates
+        .iter()
+        .map(|update| update.iter().copied().collect())
+        .collect();
+
+    // Unused rules are implied by nothing, they are listed on their own
+    let redundant = rules
+        .iter()
+        .enumerate()
+        .filter(|(idx, rule)| {
+            let mut applies = update_pages
+                .iter()
+                .filter(|pages| pages.contains(&rule.0) && pages.contains(&rule.1))
+                .peekable();
+            rules[..*idx].contains(rule)
+                || (applies.peek().is_some() && applies.all(|pages| implied(rules, rule, pages)))
+        })
+        .map(|(_, rule)| rule)
+        .collect();
+
+    let unused = rules
+        .iter()
+        .filter(|rule| {
+            !update_pages
+                .iter()
+                .any(|pages| pages.contains(&rule.0) && pages.contains(&rule.1))
+        })
+        .collect();
+
+    let rule_pages: HashSet<u32> = rules.iter().flat_map(|rule| [rule.0, rule.1]).collect();
+    let uncovered = update_pages
+        .iter()
+        .flatten()
+        .filter(|page| !rule_pages.contains(page))
+        .copied()
+        .collect::<BTreeSet<u32>>()
+        .into_iter()
+        .collect();
+
+    Audit {
+        invalid,
+        redundant,
+        unused,
+        uncovered,
+    }
+}
+
+/// Text format of [export_graph]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum GraphFormat {
+    /// Graphviz DOT
+    Dot,
+    Mermaid,
+}
+
+/// Export the rules as a directed graph, an edge `a -> b` means `a` must come before `b`
+///
+/// With an update only the rules between its pages are exported and the rules
+/// it breaks are drawn in red.
+fn export_graph(rules: &[Rule], update: Option<&[u32]>, format: GraphFormat) -> String {
+    let (pages, edges): (Vec<u32>, Vec<&Rule>) = match update {
+        Some(update) => (
+            update.to_vec(),
+            rules
+                .iter()
+                .filter(|rule| update.contains(&rule.0) && update.contains(&rule.1))
+                .collect(),
+        ),
+        None => (
+            rules
+                .iter()
+                .flat_map(|rule| [rule.0, rule.1])
+                .collect::<BTreeSet<u32>>()
+                .into_iter()
+                .collect(),
+            rules.iter().collect(),
+        ),
+    };
+    let broken: Vec<&Rule> = update
+        .map(|update| violations(rules, update))
+        .unwrap_or_default()
+        .iter()
+        .map(|violation| violation.rule)
+        .collect();
+    let is_broken = |rule: &Rule| broken.iter().any(|broken| std::ptr::eq(*broken, rule));
+
+    let mut output = String::new();
+    match format {
+        GraphFormat::Dot => {
+            output.push_str("digraph rules {\n");
+            for page in &pages {
+                output.push_str(&format!("    {page};\n"));
+            }
+            for rule in &edges {
+                let style = if is_broken(rule) { " [color=red]" } else { "" };
+                output.push_str(&format!("    {} -> {}{style};\n", rule.0, rule.1));
+            }
+            output.push_str("}\n");
+        }
+        GraphFormat::Mermaid => {
+            output.push_str("graph LR\n");
+            for page in &pages {
+                output.push_str(&format!("    p{page}[{page}]\n"));
+            }
+            for rule in &edges {
+                output.push_str(&format!("    p{} --> p{}\n", rule.0, rule.1));
+            }
+            for (idx, rule) in edges.iter().enumerate() {
+                if is_broken(rule) {
+                    output.push_str(&format!("    linkStyle {idx} stroke:red\n"));
+                }
+            }
+        }
+    }
+    output
+}
 
-    sorted
+/// Lookup of the rules, usable as a comparator to sort pages
+///
+/// Only a total order for pages that all have a rule between them, as in the puzzle input,
+/// [correct_order] handles updates with missing rules. Sorting with anything else can panic,
+/// check [PageOrder::total] first.
+#[derive(Debug, Default)]
+struct PageOrder {
+    /// Number of times each rule was added
+    rules: HashMap<(u32, u32), usize>,
 }
 
-fn puzzle_1(rules: &[Rule], updates: &[Vec<u32>]) -> u32 {
+impl PageOrder {
+    fn new(rules: &[Rule]) -> Self {
+        let mut order = Self::default();
+        for rule in rules {
+            order.add_rule(rule);
+        }
+        order
+    }
+
+    fn add_rule(&mut self, rule: &Rule) {
+        *self.rules.entry((rule.0, rule.1)).or_default() += 1;
+    }
+
+    /// Remove one occurrence of the rule, returns false if there was none
+    fn remove_rule(&mut self, rule: &Rule) -> bool {
+        let Some(count) = self.rules.get_mut(&(rule.0, rule.1)) else {
+            return false;
+        };
+        *count -= 1;
+        if *count == 0 {
+            self.rules.remove(&(rule.0, rule.1));
+        }
+        true
+    }
+
+    /// Must `left` come before `right`
+    fn before(&self, left: u32, right: u32) -> bool {
+        self.rules.contains_key(&(left, right))
+    }
+
+    /// `Less` if `left` must come before `right`, `Equal` when there is no rule between them
+    fn compare(&self, left: &u32, right: &u32) -> Ordering {
+        if self.before(*left, *right) {
+            Ordering::Less
+        } else if self.before(*right, *left) {
+            Ordering::Greater
+        } else {
+            Ordering::Equal
+        }
+    }
+
+    /// Are the pages of the update in order
+    fn valid(&self, update: &[u32]) -> bool {
+        update.iter().enumerate().all(|(idx, left)| {
+            update[idx + 1..]
+                .iter()
+                .all(|right| !self.before(*right, *left))
+        })
+    }
+
+    /// Is [PageOrder::compare] a total order of the pages, one rule between every two pages
+    /// and no cycles
+    ///
+    /// Then each page comes before a different number of the others.
+    fn total(&self, update: &[u32]) -> bool {
+        let mut before_count = vec![0; update.len()];
+        for (idx, left) in update.iter().enumerate() {
+            for (offset, right) in update[idx + 1..].iter().enumerate() {
+                match (self.before(*left, *right), self.before(*right, *left)) {
+                    (true, false) => before_count[idx] += 1,
+                    (false, true) => before_count[idx + 1 + offset] += 1,
+                    _ => return false,
+                }
+            }
+        }
+        before_count.sort_unstable();
+        before_count
+            .iter()
+            .enumerate()
+            .all(|(idx, count)| idx == *count)
+    }
+
+    fn rules(&self) -> Vec<Rule> {
+        self.rules
+            .keys()
+            .map(|(left, right)| Rule(*left, *right))
+            .collect()
+    }
+}
+
+fn puzzle_1(order: &PageOrder, updates: &[Vec<u32>]) -> u32 {
     let mut acc = 0;
     for update in updates {
-        if update_valid(rules, update) {
+        if order.valid(update) {
             assert!(
                 update.len() % 2 != 0,
                 "update has even number of pages, no middle page"
@@ -115,24 +486,66 @@ fn puzzle_1(rules: &[Rule], updates: &[Vec<u32>]) -> u32 {
     acc
 }
 
-fn puzzle_2(rules: &[Rule], updates: &[Vec<u32>]) -> u32 {
+fn puzzle_2(order: &PageOrder, updates: &[Vec<u32>]) -> u32 {
     let mut acc = 0;
     for update in updates {
-        if !update_valid(rules, update) {
-            acc += correct_order(rules, update)[update.len() / 2]
+        if !order.valid(update) {
+            let sorted = if order.total(update) {
+                let mut sorted = update.clone();
+                sorted.sort_by(|left, right| order.compare(left, right));
+                sorted
+            } else {
+                // Missing rules, the comparator is not a total order
+                correct_order(&order.rules(), update).unwrap_or_else(|cycle| panic!("{cycle}"))
+            };
+            acc += sorted[update.len() / 2]
         }
     }
     acc
 }
+
 fn main() {
     let (rules, updates) = read_input();
-    println!("{}", puzzle_1(&rules, &updates));
-    println!("{}", puzzle_2(&rules, &updates));
+    let args: Vec<String> = std::env::args().collect();
+    if args.iter().any(|arg| arg == "--audit") {
+        print!("{}", audit(&rules, &updates));
+    }
+    let update = args
+        .iter()
+        .find_map(|arg| arg.strip_prefix("--update="))
+        .map(|idx| updates[idx.parse::<usize>().expect("update index")].as_slice());
+    for (flag, format) in [
+        ("--dot", GraphFormat::Dot),
+        ("--mermaid", GraphFormat::Mermaid),
+    ] {
+        if args.iter().any(|arg| arg == flag) {
+            print!("{}", export_graph(&rules, update, format));
+        }
+    }
+
+    // Rule edits, `--add=a|b` and `--remove=a|b`
+    let mut order = PageOrder::new(&rules);
+    let parse_rule = |rule: &str| -> Rule {
+        rule.splitn(2, '|')
+            .map(|f| f.parse::<u32>().unwrap())
+            .collect()
+    };
+    for arg in &args {
+        if let Some(rule) = arg.strip_prefix("--add=") {
+            order.add_rule(&parse_rule(rule));
+        }
+        if let Some(rule) = arg.strip_prefix("--remove=") {
+            assert!(order.remove_rule(&parse_rule(rule)), "no rule {rule}");
+        }
+    }
+
+    println!("{}", puzzle_1(&order, &updates));
+    println!("{}", puzzle_2(&order, &updates));
 }
 
 #[cfg(test)]
 mod test {
-    use crate::{puzzle_1, Rule};
+    use crate::{Cycle, Rule};
 
     #[test]
     fn test_mangle() {
@@ -173,21 +586,22 @@ mod test {
         assert_eq!(updates[0], vec![75, 47, 61, 53, 29]);
         assert_eq!(updates[5], vec![97, 13, 75, 29, 47]);
 
-        assert!(crate::update_valid(&rules, &updates[0]));
-        assert!(crate::update_valid(&rules, &updates[1]));
-        assert!(crate::update_valid(&rules, &updates[2]));
-        assert!(!crate::update_valid(&rules, &updates[3]));
-        assert!(!crate::update_valid(&rules, &updates[4]));
-        assert!(!crate::update_valid(&rules, &updates[5]));
+        let order = crate::PageOrder::new(&rules);
+        assert!(order.valid(&updates[0]));
+        assert!(order.valid(&updates[1]));
+        assert!(order.valid(&updates[2]));
+        assert!(!order.valid(&updates[3]));
+        assert!(!order.valid(&updates[4]));
+        assert!(!order.valid(&updates[5]));
 
-        assert_eq!(crate::puzzle_1(&rules, &updates), 143);
+        assert_eq!(crate::puzzle_1(&order, &updates), 143);
 
         let mut ordered = Vec::new();
         for update in updates.iter() {
-            if crate::update_valid(&rules, update) {
+            if order.valid(update) {
                 ordered.push(update.clone());
             } else {
-                ordered.push(crate::correct_order(&rules, update));
+                ordered.push(crate::correct_order(&rules, update).unwrap());
             }
         }
 
@@ -203,7 +617,7 @@ mod test {
             ]
         );
 
-        assert_eq!(crate::puzzle_2(&rules, &updates), 123);
+        assert_eq!(crate::puzzle_2(&order, &updates), 123);
     }
 
     #[test]
@@ -212,6 +626,167 @@ mod test {
             &[Rule(61, 47), Rule(29, 75), Rule(61, 75), Rule(47, 53)],
             &[75, 47, 61, 53, 29],
         );
-        assert_eq!(result, vec![61, 47, 53, 29, 75])
+        assert_eq!(result, Ok(vec![61, 47, 53, 29, 75]))
+    }
+
+    #[test]
+    fn test_cycle() {
+        let rules = [Rule(1, 2), Rule(2, 3), Rule(3, 4), Rule(4, 2), Rule(5, 1)];
+        let result = crate::correct_order(&rules, &[5, 4, 3, 2, 1]);
+        assert_eq!(result, Err(Cycle(vec![2, 3, 4])));
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "rules form a cycle: 2 -> 3 -> 4 -> 2"
+        );
+
+        // Rules between pages not in the update don't apply
+        assert_eq!(crate::correct_order(&rules, &[3, 1, 2]), Ok(vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn test_audit() {
+        use crate::Violation;
+
+        let rules = [
+            Rule(1, 2),
+            Rule(2, 3),
+            Rule(1, 3), // implied by 1|2 and 2|3
+            Rule(2, 3), // repeated
+            Rule(7, 8), // never in an update together
+        ];
+        let updates = vec![vec![3, 2, 1], vec![1, 2, 3, 4], vec![7, 1]];
+        let audit = crate::audit(&rules, &updates);
+        assert_eq!(
+            audit.invalid,
+            vec![(
+                0,
+                vec![
+                    Violation {
+                        rule: &rules[0],
+                        positions: (2, 1)
+                    },
+                    Violation {
+                        rule: &rules[1],
+                        positions: (1, 0)
+                    },
+                    Violation {
+                        rule: &rules[2],
+                        positions: (2, 0)
+                    },
+                    Violation {
+                        rule: &rules[3],
+                        positions: (1, 0)
+                    },
+                ]
+            )]
+        );
+        assert_eq!(audit.redundant, vec![&rules[2], &rules[3]]);
+        assert_eq!(audit.unused, vec![&rules[4]]);
+        assert_eq!(audit.uncovered, vec![4]);
+        assert_eq!(
+            audit.invalid[0].1[0].to_string(),
+            "1|2 broken: 1 at 2 comes after 2 at 1"
+        );
+
+        // Without 2 in the update only 1|3 says where 3 goes
+        let updates = vec![vec![1, 2, 3], vec![1, 3]];
+        let audit = crate::audit(&rules, &updates);
+        assert_eq!(audit.redundant, vec![&rules[3]]);
+
+        // Chains through pages of no update don't count either
+        let rules = [Rule(1, 2), Rule(2, 3), Rule(1, 3)];
+        let audit = crate::audit(&rules, &[vec![1, 3]]);
+        assert!(audit.redundant.is_empty());
+    }
+
+    #[test]
+    fn test_export_graph() {
+        use crate::{export_graph, GraphFormat};
+
+        let rules = [Rule(1, 2), Rule(2, 3), Rule(3, 4)];
+        assert_eq!(
+            export_graph(&rules, None, GraphFormat::Dot),
+            r#"digraph rules {
+    1;
+    2;
+    3;
+    4;
+    1 -> 2;
+    2 -> 3;
+    3 -> 4;
+}
+"#
+        );
+        assert_eq!(
+            export_graph(&rules, Some(&[3, 2, 1]), GraphFormat::Dot),
+            r#"digraph rules {
+    3;
+    2;
+    1;
+    1 -> 2 [color=red];
+    2 -> 3 [color=red];
+}
+"#
+        );
+        assert_eq!(
+            export_graph(&rules, Some(&[1, 3, 2]), GraphFormat::Mermaid),
+            r#"graph LR
+    p1[1]
+    p3[3]
+    p2[2]
+    p1 --> p2
+    p2 --> p3
+    linkStyle 1 stroke:red
+"#
+        );
+    }
+
+    #[test]
+    fn test_page_order() {
+        use crate::PageOrder;
+
+        let rules = [Rule(1, 2), Rule(2, 3), Rule(1, 3)];
+        let mut order = PageOrder::new(&rules);
+        let mut update = vec![3, 1, 2];
+        assert!(!order.valid(&update));
+        update.sort_by(|left, right| order.compare(left, right));
+        assert_eq!(update, vec![1, 2, 3]);
+        assert!(order.valid(&update));
+
+        // Flip the order of 2 and 3
+        assert!(order.remove_rule(&Rule(2, 3)));
+        assert!(!order.remove_rule(&Rule(2, 3)));
+        order.add_rule(&Rule(3, 2));
+        assert!(!order.valid(&update));
+        update.sort_by(|left, right| order.compare(left, right));
+        assert_eq!(update, vec![1, 3, 2]);
+
+        // Without a rule between 1 and 2 sorting can't order them, fall back to the graph
+        let order = PageOrder::new(&[Rule(1, 3), Rule(3, 2)]);
+        assert!(!order.valid(&[2, 1, 3]));
+        assert_eq!(crate::puzzle_2(&order, &[vec![2, 1, 3]]), 3);
+
+        // Longer updates take a sort that notices the comparator isn't a total order
+        let rules: Vec<Rule> = (1..=41)
+            .flat_map(|left| (left + 1..=(left + 4).min(41)).map(move |right| Rule(left, right)))
+            .collect();
+        let order = PageOrder::new(&rules);
+        let update: Vec<u32> = (1..=41).map(|page| (page * 39) % 41 + 1).collect();
+        assert!(!order.total(&update));
+        assert_eq!(crate::puzzle_2(&order, &[update]), 21);
+
+        // A total order needs one rule between every two pages and no cycle
+        let mut order = PageOrder::new(&[Rule(1, 3), Rule(3, 2), Rule(1, 2)]);
+        assert!(order.total(&[2, 3, 1]));
+        order.add_rule(&Rule(2, 1));
+        assert!(!order.total(&[2, 3, 1]));
+        assert!(!PageOrder::new(&[Rule(1, 3), Rule(3, 2), Rule(2, 1)]).total(&[2, 3, 1]));
+
+        let mut order = PageOrder::new(&[Rule(1, 3), Rule(3, 2)]);
+        order.add_rule(&Rule(3, 2));
+
+        // A repeated rule stays until both are removed
+        assert!(order.remove_rule(&Rule(3, 2)));
+        assert!(order.before(3, 2));
     }
 }
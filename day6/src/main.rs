@@ -1,10 +1,9 @@
-use std::{thread::sleep, time::Duration};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+};
 
-fn cls() {
-    print!("\x1B[2J\x1B[1;1H");
-}
-
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Direction {
     Up,
     Down,
@@ -35,7 +34,109 @@ impl From<Direction> for char {
     }
 }
 
-#[derive(Clone)]
+impl Direction {
+    const ALL: [Direction; 4] = [
+        Direction::Up,
+        Direction::Down,
+        Direction::Left,
+        Direction::Right,
+    ];
+
+    fn turn_right(self) -> Self {
+        match self {
+            Direction::Up => Direction::Right,
+            Direction::Right => Direction::Down,
+            Direction::Down => Direction::Left,
+            Direction::Left => Direction::Up,
+        }
+    }
+
+    fn reverse(self) -> Self {
+        self.turn_right().turn_right()
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Which way the guard turns when blocked
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum Turn {
+    #[default]
+    Right,
+    Left,
+    /// Bounce back the way it came
+    Reverse,
+}
+
+impl Turn {
+    fn apply(self, dir: Direction) -> Direction {
+        match self {
+            Turn::Right => dir.turn_right(),
+            Turn::Left => dir.reverse().turn_right(),
+            Turn::Reverse => dir.reverse(),
+        }
+    }
+}
+
+/// What happens when the guard walks over the edge of the map
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum Edge {
+    /// The guard leaves the map, the walk ends
+    #[default]
+    Exit,
+    /// The edge blocks like an obstacle
+    Wall,
+    /// The guard comes back in on the opposite side
+    Wrap,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tile {
+    Floor,
+    Obstacle,
+    /// Can only be entered walking in the direction, blocks otherwise
+    OneWay(Direction),
+    /// Entering moves the guard to the other tile with the same char, keeping its direction
+    Teleport,
+}
+
+/// How the guard moves, and the meaning of the chars on the map
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Rules {
+    turn: Turn,
+    edge: Edge,
+    /// Chars not in here are floor
+    tiles: HashMap<char, Tile>,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self {
+            turn: Turn::default(),
+            edge: Edge::default(),
+            tiles: HashMap::from([('#', Tile::Obstacle)]),
+        }
+    }
+}
+
+impl Rules {
+    fn tile(&self, char: char) -> Tile {
+        self.tiles.get(&char).copied().unwrap_or(Tile::Floor)
+    }
+
+    /// Only obstacles and the puzzle's map edge, the guard walks straight segments
+    fn straight(&self, map: &[Vec<char>]) -> bool {
+        self.edge != Edge::Wrap
+            && map
+                .iter()
+                .flatten()
+                .all(|char| matches!(self.tile(*char), Tile::Floor | Tile::Obstacle))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 struct Guard {
     row: usize,
     col: usize,
@@ -45,166 +146,639 @@ struct Guard {
 #[derive(Clone)]
 struct Map {
     map: Vec<Vec<char>>,
-    guard: Guard,
+    /// Every guard on the map, the puzzle has only one
+    guards: Vec<Guard>,
+    rules: Rules,
+    /// Each teleport tile and its partner
+    teleports: HashMap<(usize, usize), (usize, usize)>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum EndGame {
     GameOver,
     Loop,
 }
 
-impl Map {
-    fn print(&self) {
-        for line in self.map.iter() {
-            println!("{}", line.iter().collect::<String>());
+/// Bitset of `(position, direction)` states of a guard on a map
+struct Visited {
+    bits: Vec<u64>,
+    width: usize,
+}
+
+impl Visited {
+    fn new(height: usize, width: usize) -> Self {
+        Self {
+            bits: vec![0; (height * width * 4).div_ceil(64)],
+            width,
+        }
+    }
+
+    fn index(&self, guard: &Guard) -> usize {
+        (guard.row * self.width + guard.col) * 4 + guard.dir.index()
+    }
+
+    /// Add the state, false if it was already visited
+    fn insert(&mut self, guard: &Guard) -> bool {
+        let idx = self.index(guard);
+        let (word, bit) = (idx / 64, 1 << (idx % 64));
+        let new = self.bits[word] & bit == 0;
+        self.bits[word] |= bit;
+        new
+    }
+}
+
+/// The states of a guard walking the map, from the start until it leaves or loops
+struct Path {
+    /// Every state in order, turning on the spot is a state of its own
+    states: Vec<Guard>,
+    end: EndGame,
+    /// Index of the state the guard returns to after the last one, if it loops
+    loop_start: Option<usize>,
+}
+
+impl Path {
+    /// The state of the guard after `time` steps, `None` once it has left
+    fn at(&self, time: usize) -> Option<&Guard> {
+        if time < self.states.len() {
+            return self.states.get(time);
         }
+        let start = self.loop_start?;
+        Some(&self.states[start + (time - start) % (self.states.len() - start)])
+    }
+
+    fn positions(&self) -> HashSet<(usize, usize)> {
+        self.states
+            .iter()
+            .map(|guard| (guard.row, guard.col))
+            .collect()
+    }
+
+    /// Index of the state where each position is reached for the first time
+    fn first_visits(&self) -> Vec<usize> {
+        let mut seen = HashSet::new();
+        (0..self.states.len())
+            .filter(|idx| seen.insert((self.states[*idx].row, self.states[*idx].col)))
+            .collect()
     }
 
     fn distinct_positions(&self) -> usize {
-        let mut counter = 0;
-        for line in self.map.iter() {
-            for char in line.iter() {
-                if !['.', '#'].contains(char) {
-                    counter += 1;
-                }
-            }
+        self.positions().len()
+    }
+}
+
+impl Map {
+    fn height(&self) -> usize {
+        self.map.len()
+    }
+
+    fn width(&self) -> usize {
+        self.map[0].len()
+    }
+
+    /// The next position in a direction, `None` off the map
+    fn neighbour(&self, row: usize, col: usize, dir: Direction) -> Option<(usize, usize)> {
+        let (row, col) = match dir {
+            Direction::Up => (row.checked_sub(1)?, col),
+            Direction::Down => (row + 1, col),
+            Direction::Left => (row, col.checked_sub(1)?),
+            Direction::Right => (row, col + 1),
+        };
+        (row < self.height() && col < self.width()).then_some((row, col))
+    }
+
+    /// The position on the opposite side of the map
+    fn wrap(&self, row: usize, col: usize, dir: Direction) -> (usize, usize) {
+        match dir {
+            Direction::Up => (self.height() - 1, col),
+            Direction::Down => (0, col),
+            Direction::Left => (row, self.width() - 1),
+            Direction::Right => (row, 0),
         }
-        counter
     }
 
-    /// Move one tile
-    fn move_guard(&mut self) -> Option<EndGame> {
-        match self.guard.dir {
-            Direction::Up => {
-                if self.guard.row == 0 {
-                    return Some(EndGame::GameOver);
+    /// The next state of the guard, `None` once it walks off the map
+    fn step(&self, guard: &Guard) -> Option<Guard> {
+        let next = match self.neighbour(guard.row, guard.col, guard.dir) {
+            Some(next) => Some(next),
+            None => match self.rules.edge {
+                Edge::Exit => return None,
+                Edge::Wall => None,
+                Edge::Wrap => Some(self.wrap(guard.row, guard.col, guard.dir)),
+            },
+        };
+        let blocked = next.is_none_or(|(row, col)| match self.rules.tile(self.map[row][col]) {
+            Tile::Obstacle => true,
+            Tile::OneWay(dir) => dir != guard.dir,
+            Tile::Floor | Tile::Teleport => false,
+        });
+        let Some(next) = next.filter(|_| !blocked) else {
+            // turn on the spot
+            return Some(Guard {
+                dir: self.rules.turn.apply(guard.dir),
+                ..guard.clone()
+            });
+        };
+        let (row, col) = self.teleports.get(&next).copied().unwrap_or(next);
+        Some(Guard {
+            row,
+            col,
+            dir: guard.dir,
+        })
+    }
+
+    /// Switch to other rules, pairing up the teleport tiles
+    fn with_rules(self, rules: Rules) -> Self {
+        let mut tiles: HashMap<char, Vec<(usize, usize)>> = HashMap::new();
+        for (row, line) in self.map.iter().enumerate() {
+            for (col, char) in line.iter().enumerate() {
+                if rules.tile(*char) == Tile::Teleport {
+                    tiles.entry(*char).or_default().push((row, col));
                 }
-                self.guard.row -= 1;
-            }
-            Direction::Down => {
-                self.guard.row += 1;
-                if self.guard.row == self.map.len() {
-                    return Some(EndGame::GameOver);
-                };
             }
-            Direction::Left => {
-                if self.guard.col == 0 {
-                    return Some(EndGame::GameOver);
+        }
+        let mut teleports = HashMap::new();
+        for (char, positions) in tiles {
+            let [one, other] = positions[..] else {
+                panic!("teleport {char} is not a pair");
+            };
+            teleports.insert(one, other);
+            teleports.insert(other, one);
+        }
+        Self {
+            rules,
+            teleports,
+            ..self
+        }
+    }
+
+    /// Free positions for an obstacle, a guard's start is not one of them
+    fn floor(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        (0..self.height())
+            .flat_map(|row| (0..self.width()).map(move |col| (row, col)))
+            .filter(|(row, col)| {
+                self.rules.tile(self.map[*row][*col]) == Tile::Floor
+                    && !self
+                        .guards
+                        .iter()
+                        .any(|guard| (guard.row, guard.col) == (*row, *col))
+            })
+    }
+
+    fn with_obstacle(&self, (row, col): (usize, usize)) -> Self {
+        let mut map = self.clone();
+        map.map[row][col] = '#';
+        map
+    }
+
+    /// Walk the (first) guard from its starting position, leaving the map untouched
+    fn walk(&self) -> Path {
+        self.walk_from(&self.guards[0])
+    }
+
+    fn walk_from(&self, guard: &Guard) -> Path {
+        let mut guard = guard.clone();
+        let mut visited = Visited::new(self.height(), self.width());
+        let mut states = Vec::new();
+        visited.insert(&guard);
+        states.push(guard.clone());
+        let (end, loop_start) = loop {
+            match self.step(&guard) {
+                None => break (EndGame::GameOver, None),
+                Some(next) if !visited.insert(&next) => {
+                    break (
+                        EndGame::Loop,
+                        states.iter().position(|state| *state == next),
+                    );
                 }
-                self.guard.col -= 1;
-            }
-            Direction::Right => {
-                self.guard.col += 1;
-                if self.guard.col == self.map[0].len() {
-                    return Some(EndGame::GameOver);
+                Some(next) => {
+                    states.push(next.clone());
+                    guard = next;
                 }
             }
         };
-        if self.map[self.guard.row][self.guard.col] == '#' {
-            // invalid move, undo change, turn right, and try again
-            match self.guard.dir {
-                Direction::Up => {
-                    self.guard.row += 1;
-                    self.guard.dir = Direction::Right
-                }
-                Direction::Down => {
-                    self.guard.row -= 1;
-                    self.guard.dir = Direction::Left
-                }
-                Direction::Left => {
-                    self.guard.col += 1;
-                    self.guard.dir = Direction::Up
-                }
-                Direction::Right => {
-                    self.guard.col -= 1;
-                    self.guard.dir = Direction::Down
-                }
-            };
-            return self.move_guard();
+        Path {
+            states,
+            end,
+            loop_start,
         }
+    }
+}
 
-        let new_location = self.map[self.guard.row][self.guard.col];
-        match self.guard.dir {
-            Direction::Up if new_location == '^' => {
-                return Some(EndGame::Loop);
-            }
-            Direction::Down if new_location == 'v' => {
-                return Some(EndGame::Loop);
+/// For every position and direction, where a guard walking that way stops in front of an
+/// obstacle, so it can move a whole segment at a time
+///
+/// Only for [Rules::straight] maps
+struct Jumps {
+    width: usize,
+    /// `None` when the guard walks off the map
+    stops: Vec<[Option<(usize, usize)>; 4]>,
+}
+
+impl Jumps {
+    fn new(map: &Map) -> Self {
+        let (height, width) = (map.height(), map.width());
+        let mut stops = vec![[None; 4]; height * width];
+        for dir in Direction::ALL {
+            // fill in the neighbour in the walking direction first
+            let mut order = (0..height)
+                .flat_map(|row| (0..width).map(move |col| (row, col)))
+                .collect::<Vec<_>>();
+            if matches!(dir, Direction::Down | Direction::Right) {
+                order.reverse();
             }
-            Direction::Left if new_location == '<' => {
-                return Some(EndGame::Loop);
+            for (row, col) in order {
+                stops[row * width + col][dir.index()] = match map.neighbour(row, col, dir) {
+                    None if map.rules.edge == Edge::Wall => Some((row, col)),
+                    None => None,
+                    Some((r, c)) if map.rules.tile(map.map[r][c]) == Tile::Obstacle => {
+                        Some((row, col))
+                    }
+                    Some((r, c)) => stops[r * width + c][dir.index()],
+                };
             }
-            Direction::Right if new_location == '>' => {
-                return Some(EndGame::Loop);
+        }
+        Self { width, stops }
+    }
+
+    /// Where the guard stops walking straight, with an extra obstacle placed on the map
+    fn stop(&self, guard: &Guard, obstacle: (usize, usize)) -> Option<(usize, usize)> {
+        let stop = self.stops[guard.row * self.width + guard.col][guard.dir.index()];
+        let (row, col) = (guard.row, guard.col);
+        let (o_row, o_col) = obstacle;
+        // does the obstacle cut the segment short
+        let blocked = match guard.dir {
+            Direction::Up => o_col == col && o_row < row && stop.is_none_or(|(r, _)| r <= o_row),
+            Direction::Down => o_col == col && o_row > row && stop.is_none_or(|(r, _)| o_row <= r),
+            Direction::Left => o_row == row && o_col < col && stop.is_none_or(|(_, c)| c <= o_col),
+            Direction::Right => o_row == row && o_col > col && stop.is_none_or(|(_, c)| o_col <= c),
+        };
+        if !blocked {
+            return stop;
+        }
+        Some(match guard.dir {
+            Direction::Up => (o_row + 1, col),
+            Direction::Down => (o_row - 1, col),
+            Direction::Left => (row, o_col + 1),
+            Direction::Right => (row, o_col - 1),
+        })
+    }
+
+    /// Does the guard loop when starting from `guard` with an extra obstacle
+    fn loops(&self, map: &Map, guard: &Guard, obstacle: (usize, usize)) -> bool {
+        let mut visited = Visited::new(map.height(), map.width());
+        let mut guard = guard.clone();
+        while let Some((row, col)) = self.stop(&guard, obstacle) {
+            guard = Guard {
+                row,
+                col,
+                dir: map.rules.turn.apply(guard.dir),
+            };
+            if !visited.insert(&guard) {
+                return true;
             }
-            _ => {}
         }
-        self.map[self.guard.row][self.guard.col] = self.guard.dir.into();
-        None
+        false
     }
 }
 
 fn mangle(input: &str) -> Map {
     let input = input.trim();
     let mut map = Vec::new();
-    let mut guard: Option<Guard> = None;
+    let mut guards = Vec::new();
     for (row, line) in input.lines().enumerate() {
         // Add the line to the map
         map.push(line.chars().collect::<Vec<char>>());
-        // see if the starting position is on this line
+        // see if guards start on this line
         for (col, char) in line.chars().enumerate() {
             if ['<', '^', '>', 'v'].contains(&char) {
-                guard = Some(Guard {
+                guards.push(Guard {
                     row,
                     col,
                     dir: char.into(),
                 });
-                break;
-            }
-        }
-    }
-    let guard = guard.expect("Did not find the guard");
-    Map { map, guard }
-}
-
-fn puzzle_1(map: &mut Map) -> usize {
-    while map.move_guard().is_none() {}
-    map.distinct_positions()
-}
-
-fn puzzle_2(original_map: &mut Map) -> usize {
-    let mut counter = 0;
-    for (row, line) in original_map.map.iter().enumerate() {
-        for (col, position) in line.iter().enumerate() {
-            if *position != '#' && (row, col) != (original_map.guard.row, original_map.guard.col) {
-                let mut map = original_map.clone();
-                map.map[row][col] = '#';
-                loop {
-                    match map.move_guard() {
-                        None => {
-                            // sleep(Duration::from_millis(1));
-                            // cls();
-                            // println!("Loops: {counter}");
-                            // map.print();
-                        }
-                        Some(EndGame::GameOver) => break,
-                        Some(EndGame::Loop) => {
-                            counter += 1;
-                            break;
-                        }
+            }
+        }
+    }
+    assert!(!guards.is_empty(), "Did not find the guard");
+    Map {
+        map,
+        guards,
+        rules: Rules::default(),
+        teleports: HashMap::new(),
+    }
+}
+
+/// Coverage of several guards walking the map at the same time
+///
+/// Guards walk through each other, a collision is two guards on the same position or two
+/// guards swapping positions as they walk into each other.
+struct Patrol {
+    paths: Vec<Path>,
+    /// Positions covered by each guard
+    covered: Vec<HashSet<(usize, usize)>>,
+    /// First time two guards collide, and where the first of them is then
+    first_collision: Option<(usize, (usize, usize))>,
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+impl Patrol {
+    fn new(map: &Map) -> Self {
+        let paths = map
+            .guards
+            .iter()
+            .map(|guard| map.walk_from(guard))
+            .collect::<Vec<_>>();
+        let covered = paths.iter().map(Path::positions).collect();
+        let mut first_collision: Option<(usize, (usize, usize))> = None;
+        for (idx, one) in paths.iter().enumerate() {
+            for other in &paths[idx + 1..] {
+                if let Some(collision) = Self::collision(one, other) {
+                    if first_collision.is_none_or(|(time, _)| collision.0 < time) {
+                        first_collision = Some(collision);
                     }
                 }
             }
         }
+        Self {
+            paths,
+            covered,
+            first_collision,
+        }
     }
-    counter
+
+    /// First time two guards are on the same position, or have just swapped positions
+    fn collision(one: &Path, other: &Path) -> Option<(usize, (usize, usize))> {
+        // Once both are in their loops the positions repeat, one more step to see the swap
+        // back into the start of the loops
+        let horizon = match (one.loop_start, other.loop_start) {
+            (Some(one_start), Some(other_start)) => {
+                let (one_len, other_len) = (
+                    one.states.len() - one_start,
+                    other.states.len() - other_start,
+                );
+                one_start.max(other_start) + one_len / gcd(one_len, other_len) * other_len + 1
+            }
+            (None, None) => one.states.len().min(other.states.len()),
+            (None, Some(_)) => one.states.len(),
+            (Some(_), None) => other.states.len(),
+        };
+        (0..horizon).find_map(|time| {
+            let (one_now, other_now) = (one.at(time)?, other.at(time)?);
+            let position = (one_now.row, one_now.col);
+            let other_position = (other_now.row, other_now.col);
+            if position == other_position {
+                return Some((time, position));
+            }
+            let (one_before, other_before) = (one.at(time.checked_sub(1)?)?, other.at(time - 1)?);
+            (position == (other_before.row, other_before.col)
+                && other_position == (one_before.row, one_before.col))
+                .then_some((time, position))
+        })
+    }
+
+    /// Positions covered by any guard
+    fn union(&self) -> HashSet<(usize, usize)> {
+        self.covered.iter().flatten().copied().collect()
+    }
+
+    /// Positions covered by more than one guard
+    fn shared(&self) -> HashSet<(usize, usize)> {
+        let mut seen = HashSet::new();
+        self.covered
+            .iter()
+            .flatten()
+            .filter(|position| !seen.insert(**position))
+            .copied()
+            .collect()
+    }
+
+    /// The guards never all leave, so the joint state repeats
+    fn loops(&self) -> bool {
+        self.paths.iter().any(|path| path.end == EndGame::Loop)
+    }
+}
+
+impl Display for Patrol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (path, covered) in self.paths.iter().zip(&self.covered) {
+            let guard = &path.states[0];
+            let end = match path.end {
+                EndGame::GameOver => "leaves",
+                EndGame::Loop => "loops",
+            };
+            writeln!(
+                f,
+                "guard {} at ({}, {}): {} positions, {end}",
+                char::from(guard.dir),
+                guard.row,
+                guard.col,
+                covered.len()
+            )?;
+        }
+        writeln!(f, "union: {} positions", self.union().len())?;
+        writeln!(f, "shared: {} positions", self.shared().len())?;
+        match self.first_collision {
+            Some((time, (row, col))) => writeln!(f, "first collision: {time} at ({row}, {col})")?,
+            None => writeln!(f, "no collision")?,
+        }
+        write!(
+            f,
+            "{}",
+            if self.loops() {
+                "the guards loop"
+            } else {
+                "all guards leave"
+            }
+        )
+    }
+}
+
+fn puzzle_1(map: &Map) -> usize {
+    map.walk().distinct_positions()
+}
+
+/// Positions where an obstacle makes the (first) guard loop
+fn loop_obstacles(map: &Map) -> Vec<(usize, usize)> {
+    // An obstacle off the path never changes it, and until the guard first reaches the
+    // obstacle's position the walk is the same as without it
+    let path = map.walk();
+    let jumps = map.rules.straight(&map.map).then(|| Jumps::new(map));
+    let floor = map.floor().collect::<HashSet<_>>();
+    let mut on_path = HashSet::new();
+    let mut obstacles = Vec::new();
+    for idx in path.first_visits().into_iter().skip(1) {
+        let obstacle = (path.states[idx].row, path.states[idx].col);
+        on_path.insert(obstacle);
+        if !floor.contains(&obstacle) {
+            continue;
+        }
+        let before = &path.states[idx - 1];
+        let loops = match &jumps {
+            Some(jumps) => jumps.loops(map, before, obstacle),
+            None => map.with_obstacle(obstacle).walk_from(before).end == EndGame::Loop,
+        };
+        if loops {
+            obstacles.push(obstacle);
+        }
+    }
+    if path.end == EndGame::Loop {
+        // The guard loops already, so does it with an obstacle anywhere off the path
+        obstacles.extend(floor.difference(&on_path));
+    }
+    obstacles.sort_unstable();
+    obstacles
+}
+
+/// The loop a guard ends up in
+#[derive(Debug, PartialEq, Eq)]
+struct Cycle {
+    /// The first state of the guard in the loop
+    entry: Guard,
+    /// Number of steps around the loop, turning on the spot counts as a step
+    length: usize,
+    /// Positions of the loop in the order the guard first reaches them
+    cells: Vec<(usize, usize)>,
+}
+
+/// An obstacle that makes the guard loop
+struct Obstruction {
+    position: (usize, usize),
+    /// The walk of the guard with the obstacle placed
+    path: Path,
+}
+
+impl Obstruction {
+    fn cycle(&self) -> Cycle {
+        let start = self.path.loop_start.expect("the guard loops");
+        let states = &self.path.states[start..];
+        let mut seen = HashSet::new();
+        Cycle {
+            entry: states[0].clone(),
+            length: states.len(),
+            cells: states
+                .iter()
+                .map(|guard| (guard.row, guard.col))
+                .filter(|position| seen.insert(*position))
+                .collect(),
+        }
+    }
+
+    /// The map with the obstacle as `O` and the guard's walk drawn with `|`, `-` and `+`
+    fn render(&self, map: &Map) -> String {
+        let (mut vertical, mut horizontal) = (HashSet::new(), HashSet::new());
+        for guard in &self.path.states {
+            match guard.dir {
+                Direction::Up | Direction::Down => vertical.insert((guard.row, guard.col)),
+                Direction::Left | Direction::Right => horizontal.insert((guard.row, guard.col)),
+            };
+        }
+        let start = &self.path.states[0];
+        let mut out = String::new();
+        for (row, line) in map.map.iter().enumerate() {
+            for (col, char) in line.iter().enumerate() {
+                let position = (row, col);
+                out.push(match position {
+                    _ if position == self.position => 'O',
+                    _ if position == (start.row, start.col) => start.dir.into(),
+                    _ => match (vertical.contains(&position), horizontal.contains(&position)) {
+                        (true, true) => '+',
+                        (true, false) => '|',
+                        (false, true) => '-',
+                        (false, false) => *char,
+                    },
+                });
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// Every obstacle that makes the (first) guard loop, with the walk it takes
+fn obstructions(map: &Map) -> Vec<Obstruction> {
+    loop_obstacles(map)
+        .into_iter()
+        .map(|position| Obstruction {
+            position,
+            path: map.with_obstacle(position).walk(),
+        })
+        .collect()
+}
+
+fn puzzle_2(map: &Map) -> usize {
+    loop_obstacles(map).len()
+}
+
+/// Rules from the command line: `--turn=left|right|reverse`, `--edge=exit|wall|wrap`,
+/// `--teleport=<chars>` and `--one-way=<char><^v<>>`
+fn parse_rules(args: &[String]) -> Rules {
+    let mut rules = Rules::default();
+    for arg in args {
+        if let Some(turn) = arg.strip_prefix("--turn=") {
+            rules.turn = match turn {
+                "right" => Turn::Right,
+                "left" => Turn::Left,
+                "reverse" => Turn::Reverse,
+                other => panic!("unknown turn {other}"),
+            };
+        } else if let Some(edge) = arg.strip_prefix("--edge=") {
+            rules.edge = match edge {
+                "exit" => Edge::Exit,
+                "wall" => Edge::Wall,
+                "wrap" => Edge::Wrap,
+                other => panic!("unknown edge {other}"),
+            };
+        } else if let Some(chars) = arg.strip_prefix("--teleport=") {
+            rules
+                .tiles
+                .extend(chars.chars().map(|char| (char, Tile::Teleport)));
+        } else if let Some(tile) = arg.strip_prefix("--one-way=") {
+            let [char, dir] = tile.chars().collect::<Vec<_>>()[..] else {
+                panic!("invalid one-way tile {tile}");
+            };
+            rules.tiles.insert(char, Tile::OneWay(dir.into()));
+        }
+    }
+    rules
 }
 
 fn main() {
-    let mut map = mangle(&std::fs::read_to_string("input.txt").unwrap());
-    // println!("{}", puzzle_1(&mut map));
+    let args = std::env::args().collect::<Vec<_>>();
+    let map = mangle(&std::fs::read_to_string("input.txt").unwrap()).with_rules(parse_rules(&args));
+    // `--obstructions` lists the loops, `--render` draws them
+    let render = args.iter().any(|arg| arg == "--render");
+    if render || args.iter().any(|arg| arg == "--obstructions") {
+        for obstruction in obstructions(&map) {
+            let (row, col) = obstruction.position;
+            let cycle = obstruction.cycle();
+            println!(
+                "O at ({row}, {col}): loop of {} steps over {} cells, entered at ({}, {}) {}",
+                cycle.length,
+                cycle.cells.len(),
+                cycle.entry.row,
+                cycle.entry.col,
+                char::from(cycle.entry.dir)
+            );
+            if render {
+                println!("{}", obstruction.render(&map));
+            }
+        }
+        return;
+    }
+    if args.iter().any(|arg| arg == "--patrol") {
+        println!("{}", Patrol::new(&map));
+        return;
+    }
+    println!("{}", puzzle_1(&map));
 
-    let result = puzzle_2(&mut map);
+    let result = puzzle_2(&map);
     println!("Loops: {result}")
 }
 
@@ -212,6 +786,16 @@ fn main() {
 mod test {
     use super::*;
 
+    /// Pseudo random numbers for the random tests, the same on every run
+    fn random(mut seed: u64) -> impl FnMut() -> usize {
+        move || {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) as usize
+        }
+    }
+
     #[test]
     fn test_map() {
         let input = r#"
@@ -226,9 +810,279 @@ mod test {
 #.........
 ......#...
 "#;
-        let mut map = mangle(input);
-        map.print();
-        while map.move_guard().is_none() {}
-        assert_eq!(map.distinct_positions(), 41);
+        let map = mangle(input);
+        let path = map.walk();
+        assert_eq!(path.end, EndGame::GameOver);
+        assert_eq!(path.distinct_positions(), 41);
+        assert_eq!(puzzle_1(&map), 41);
+        // The map is left as it was, part 2 can run on it
+        assert_eq!(map.map, mangle(input).map);
+        assert_eq!(puzzle_2(&map), 6);
+    }
+
+    #[test]
+    fn test_obstructions() {
+        let input = r#"
+....#.....
+.........#
+..........
+..#.......
+.......#..
+..........
+.#..^.....
+........#.
+#.........
+......#...
+"#;
+        let map = mangle(input);
+        let obstructions = obstructions(&map);
+        let positions = obstructions.iter().map(|o| o.position).collect::<Vec<_>>();
+        assert_eq!(positions, [(6, 3), (7, 6), (7, 7), (8, 1), (8, 3), (9, 7)]);
+
+        let cycle = obstructions[0].cycle();
+        assert_eq!(cycle.entry, map.guards[0]);
+        assert_eq!(cycle.length, 22);
+        assert_eq!(cycle.cells.len(), 18);
+        assert_eq!(
+            obstructions[0].render(&map),
+            r#"....#.....
+....+---+#
+....|...|.
+..#.|...|.
+....|..#|.
+....|...|.
+.#.O^---+.
+........#.
+#.........
+......#...
+"#
+        );
+        assert_eq!(
+            obstructions[5].render(&map),
+            r#"....#.....
+....+---+#
+....|...|.
+..#.|...|.
+..+-+-+#|.
+..|.|.|.|.
+.#+-^-+-+.
+.+----++#.
+#+----++..
+......#O..
+"#
+        );
+    }
+
+    #[test]
+    fn test_loop() {
+        // The guard crosses its own path going right before it loops
+        let input = r#"
+.#....
+.....#
+#.....
+.^..#.
+"#;
+        let map = mangle(input);
+        let path = map.walk();
+        assert_eq!(path.end, EndGame::Loop);
+        // (2, 1) is crossed going up, and reached again going left where turning closes the loop
+        let crossings = path
+            .states
+            .iter()
+            .filter(|guard| (guard.row, guard.col) == (2, 1));
+        assert_eq!(crossings.count(), 2);
+        assert_eq!(path.distinct_positions(), 9);
+    }
+
+    #[test]
+    fn test_rules() {
+        let input = r#"
+#.>..#
+..T...
+.....T
+..u...
+"#;
+        // Bounce between the obstacles
+        let rules = Rules {
+            turn: Turn::Reverse,
+            ..Rules::default()
+        };
+        let path = mangle(input).with_rules(rules.clone()).walk();
+        assert_eq!(path.end, EndGame::Loop);
+        assert_eq!(path.distinct_positions(), 4);
+
+        // Turning left inside walls the guard circles the map
+        let rules = Rules {
+            turn: Turn::Left,
+            edge: Edge::Wall,
+            ..Rules::default()
+        };
+        let path = mangle(input).with_rules(rules).walk();
+        assert_eq!(path.end, EndGame::Loop);
+        assert_eq!(path.distinct_positions(), 19);
+
+        // Without obstacles the guard wraps around the row forever
+        let rules = Rules {
+            edge: Edge::Wrap,
+            ..Rules::default()
+        };
+        let map = mangle(&input.replace('#', ".")).with_rules(rules);
+        let path = map.walk();
+        assert_eq!(path.end, EndGame::Loop);
+        assert_eq!(path.distinct_positions(), 6);
+
+        // Through the teleport from (1, 2) to (2, 5)
+        let mut rules = Rules {
+            turn: Turn::Left,
+            edge: Edge::Wall,
+            ..Rules::default()
+        };
+        rules.tiles.insert('T', Tile::Teleport);
+        rules.tiles.insert('u', Tile::OneWay(Direction::Up));
+        let map = mangle(&input.replace(">", "v")).with_rules(rules);
+        let path = map.walk();
+        let cells = path
+            .states
+            .iter()
+            .map(|guard| (guard.row, guard.col))
+            .collect::<Vec<_>>();
+        assert_eq!(&cells[..3], [(0, 2), (2, 5), (3, 5)]);
+    }
+
+    #[test]
+    fn test_patrol() {
+        let input = r#"
+..v..
+.....
+>....
+.....
+"#;
+        let patrol = Patrol::new(&mangle(input));
+        assert_eq!(patrol.covered[0].len(), 4);
+        assert_eq!(patrol.covered[1].len(), 5);
+        assert_eq!(patrol.union().len(), 8);
+        assert_eq!(patrol.shared(), HashSet::from([(2, 2)]));
+        assert_eq!(patrol.first_collision, Some((2, (2, 2))));
+
+        // Walking into each other
+        let patrol = Patrol::new(&mangle("...><..."));
+        assert_eq!(patrol.first_collision, Some((1, (0, 4))));
+        let patrol = Patrol::new(&mangle("..>..<.."));
+        assert_eq!(patrol.first_collision, Some((2, (0, 4))));
+        assert!(!patrol.loops());
+
+        // One guard walks off the map, the other is stuck in a loop
+        let input = r#"
+.#....
+.....#
+#.v...
+.^..#.
+"#;
+        let patrol = Patrol::new(&mangle(input));
+        assert_eq!(patrol.paths[0].end, EndGame::GameOver);
+        assert_eq!(patrol.paths[1].end, EndGame::Loop);
+        assert!(patrol.loops());
+    }
+
+    #[test]
+    fn test_collisions_match_simulation() {
+        let mut next = random(7);
+        for _ in 0..200 {
+            let (height, width) = (3 + next() % 6, 3 + next() % 6);
+            let input = (0..height)
+                .map(|_| {
+                    (0..width)
+                        .map(|_| match next() % 14 {
+                            0 => '^',
+                            1 => '>',
+                            2 => 'v',
+                            3 => '<',
+                            4..=6 => '#',
+                            _ => '.',
+                        })
+                        .collect::<String>()
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            if !input.contains(['^', '>', 'v', '<']) {
+                continue;
+            }
+            let map = mangle(&input);
+            let patrol = Patrol::new(&map);
+            // Step all guards together until a collision or every guard has left, guards
+            // meet on a position or swap positions
+            let mut guards = map.guards.iter().cloned().map(Some).collect::<Vec<_>>();
+            let mut before: Vec<Option<(usize, usize)>> = vec![None; guards.len()];
+            let mut expected = None;
+            for time in 0..10_000 {
+                let positions = guards
+                    .iter()
+                    .map(|guard| guard.as_ref().map(|guard| (guard.row, guard.col)))
+                    .collect::<Vec<_>>();
+                let collision = (0..positions.len())
+                    .flat_map(|one| (one + 1..positions.len()).map(move |other| (one, other)))
+                    .find_map(|(one, other)| {
+                        let (position, other_position) = (positions[one]?, positions[other]?);
+                        let swapped =
+                            Some(position) == before[other] && Some(other_position) == before[one];
+                        (position == other_position || swapped).then_some(position)
+                    });
+                if let Some(position) = collision {
+                    expected = Some((time, position));
+                    break;
+                }
+                before = positions;
+                guards = guards
+                    .iter()
+                    .map(|guard| map.step(guard.as_ref()?))
+                    .collect();
+            }
+            assert_eq!(patrol.first_collision, expected, "{input}");
+        }
+    }
+
+    /// Place an obstacle on every free cell and walk the whole map
+    fn brute_force(map: &Map) -> usize {
+        map.floor()
+            .filter(|obstacle| map.with_obstacle(*obstacle).walk().end == EndGame::Loop)
+            .count()
+    }
+
+    #[test]
+    fn test_obstacles_match_brute_force() {
+        // Small pseudo random maps, with the guard facing each way
+        let mut next = random(42);
+        for round in 0..600 {
+            let (height, width) = (3 + next() % 8, 3 + next() % 8);
+            let mut input = String::new();
+            let guard = next() % (height * width);
+            // the second half adds a teleport pair `T` and one-way tiles `u`
+            let teleports = [next() % (height * width), next() % (height * width)];
+            let special =
+                round >= 300 && !teleports.contains(&guard) && teleports[0] != teleports[1];
+            for cell in 0..height * width {
+                input.push(match cell {
+                    cell if cell == guard => ['^', 'v', '<', '>'][next() % 4],
+                    cell if special && teleports.contains(&cell) => 'T',
+                    _ if next().is_multiple_of(5) => '#',
+                    _ if special && next().is_multiple_of(10) => 'u',
+                    _ => '.',
+                });
+                if cell % width == width - 1 {
+                    input.push('\n');
+                }
+            }
+            let mut rules = Rules::default();
+            if round >= 100 {
+                rules.turn = [Turn::Right, Turn::Left, Turn::Reverse][next() % 3];
+                rules.edge = [Edge::Exit, Edge::Wall, Edge::Wrap][next() % 3];
+            }
+            if special {
+                rules.tiles.insert('T', Tile::Teleport);
+                rules.tiles.insert('u', Tile::OneWay(Direction::Up));
+            }
+            let map = mangle(&input).with_rules(rules.clone());
+            assert_eq!(puzzle_2(&map), brute_force(&map), "{input}{rules:?}");
+        }
     }
 }
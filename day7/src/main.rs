@@ -1,75 +1,372 @@
 use core::panic;
-use std::ops::{Add, AddAssign};
+use std::{
+    collections::BTreeSet,
+    fmt::{Debug, Display},
+    ops::ControlFlow,
+    str::FromStr,
+};
 
-#[derive(Clone, Debug, PartialEq)]
+use num_bigint::BigUint;
+
+/// The numbers equations are solved in, every operation is checked and `None` when the result
+/// can't be represented
+///
+/// An equation only holds when every step of it can be represented, so an overflow is a dead
+/// end even where a later `* 0` would bring it back down.
+trait Number: Clone + Ord + Debug + Display + FromStr<Err: Debug> {
+    fn from_u32(value: u32) -> Self;
+    fn plus(&self, rhs: &Self) -> Option<Self>;
+    fn minus(&self, rhs: &Self) -> Option<Self>;
+    fn times(&self, rhs: &Self) -> Option<Self>;
+    /// Rounding down, `None` dividing by zero
+    fn divide(&self, rhs: &Self) -> Option<Self>;
+    fn remainder(&self, rhs: &Self) -> Option<Self>;
+    fn power(&self, exp: &Self) -> Option<Self>;
+    /// The exact integer `exp`th root
+    fn root(&self, exp: &Self) -> Option<Self>;
+    /// Number of decimal digits, zero has one
+    fn digits(&self) -> u32;
+}
+
+impl Number for usize {
+    fn from_u32(value: u32) -> Self {
+        value as usize
+    }
+
+    fn plus(&self, rhs: &Self) -> Option<Self> {
+        self.checked_add(*rhs)
+    }
+
+    fn minus(&self, rhs: &Self) -> Option<Self> {
+        self.checked_sub(*rhs)
+    }
+
+    fn times(&self, rhs: &Self) -> Option<Self> {
+        self.checked_mul(*rhs)
+    }
+
+    fn divide(&self, rhs: &Self) -> Option<Self> {
+        self.checked_div(*rhs)
+    }
+
+    fn remainder(&self, rhs: &Self) -> Option<Self> {
+        self.checked_rem(*rhs)
+    }
+
+    fn power(&self, exp: &Self) -> Option<Self> {
+        self.checked_pow(u32::try_from(*exp).ok()?)
+    }
+
+    fn root(&self, exp: &Self) -> Option<Self> {
+        if *exp == 0 {
+            return None;
+        }
+        // Binary search for the largest number with a power no more than `self`
+        let (mut low, mut high) = (0, *self);
+        while low < high {
+            let mid = low + (high - low).div_ceil(2);
+            match mid.power(exp) {
+                Some(power) if power <= *self => low = mid,
+                _ => high = mid - 1,
+            }
+        }
+        (low.power(exp) == Some(*self)).then_some(low)
+    }
+
+    fn digits(&self) -> u32 {
+        self.checked_ilog10().unwrap_or(0) + 1
+    }
+}
+
+/// Powers of big numbers stop here, larger ones would never fit in memory
+const MAX_BITS: u64 = 1 << 24;
+
+impl Number for BigUint {
+    fn from_u32(value: u32) -> Self {
+        BigUint::from(value)
+    }
+
+    fn plus(&self, rhs: &Self) -> Option<Self> {
+        Some(self + rhs)
+    }
+
+    fn minus(&self, rhs: &Self) -> Option<Self> {
+        (self >= rhs).then(|| self - rhs)
+    }
+
+    fn times(&self, rhs: &Self) -> Option<Self> {
+        Some(self * rhs)
+    }
+
+    fn divide(&self, rhs: &Self) -> Option<Self> {
+        (*rhs != BigUint::ZERO).then(|| self / rhs)
+    }
+
+    fn remainder(&self, rhs: &Self) -> Option<Self> {
+        (*rhs != BigUint::ZERO).then(|| self % rhs)
+    }
+
+    fn power(&self, exp: &Self) -> Option<Self> {
+        let exp = u32::try_from(exp).ok()?;
+        (self.bits() * u64::from(exp) <= MAX_BITS).then(|| self.pow(exp))
+    }
+
+    fn root(&self, exp: &Self) -> Option<Self> {
+        let root = self.nth_root(u32::try_from(exp).ok()?);
+        (root.pow(u32::try_from(exp).ok()?) == *self).then_some(root)
+    }
+
+    fn digits(&self) -> u32 {
+        self.to_string().len() as u32
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 enum Operator {
     Add,
     Mul,
     Concat,
+    Sub,
+    /// Integer division, rounding down
+    Div,
+    Pow,
+}
+
+impl Operator {
+    const ALL: [Operator; 6] = [
+        Operator::Add,
+        Operator::Mul,
+        Operator::Concat,
+        Operator::Sub,
+        Operator::Div,
+        Operator::Pow,
+    ];
+
+    /// `None` when the result isn't a natural number, or doesn't fit
+    fn apply<N: Number>(self, lhs: &N, rhs: &N) -> Option<N> {
+        match self {
+            Operator::Add => lhs.plus(rhs),
+            Operator::Mul => lhs.times(rhs),
+            Operator::Concat => lhs.times(&shift(rhs)?)?.plus(rhs),
+            Operator::Sub => lhs.minus(rhs),
+            Operator::Div => lhs.divide(rhs),
+            Operator::Pow => lhs.power(rhs),
+        }
+    }
+
+    /// The values of `lhs` for which `lhs <op> rhs == target`
+    fn undo<N: Number>(self, target: &N, rhs: &N) -> Undo<N> {
+        let one = |lhs: Option<N>| match lhs {
+            Some(lhs) => Undo::Lhs {
+                to: Some(lhs.clone()),
+                from: lhs,
+            },
+            None => Undo::Impossible,
+        };
+        let (zero, unit) = (N::from_u32(0), N::from_u32(1));
+        match self {
+            Operator::Add => one(target.minus(rhs)),
+            Operator::Mul if *rhs == zero && *target == zero => Undo::Any,
+            Operator::Mul => one(target
+                .divide(rhs)
+                .filter(|lhs| lhs.times(rhs).as_ref() == Some(target))),
+            Operator::Concat => one(shift(rhs).and_then(|shift| {
+                (target.remainder(&shift).as_ref() == Some(rhs))
+                    .then(|| target.divide(&shift))
+                    .flatten()
+            })),
+            Operator::Sub => one(target.plus(rhs)),
+            Operator::Div if *rhs == zero => Undo::Impossible,
+            Operator::Div => match target.times(rhs) {
+                Some(from) => Undo::Lhs {
+                    to: from.plus(&rhs.minus(&unit).unwrap()),
+                    from,
+                },
+                None => Undo::Impossible,
+            },
+            Operator::Pow if *rhs == zero && *target == unit => Undo::Any,
+            Operator::Pow if *rhs == zero => Undo::Impossible,
+            Operator::Pow => one(target.root(rhs)),
+        }
+    }
+}
+
+/// Where undoing an operator leaves the left hand side
+enum Undo<N> {
+    Impossible,
+    /// Every value from `from` up to `to`, `None` if that is past the largest number
+    Lhs {
+        from: N,
+        to: Option<N>,
+    },
+    /// Any value works, like anything times zero
+    Any,
+}
+
+impl<N: Number> Undo<N> {
+    /// The possible values of `lhs`, only [Undo::Lhs] has a list of them
+    fn values(self) -> impl Iterator<Item = N> {
+        let (from, to) = match self {
+            Undo::Lhs { from, to } => (Some(from), to),
+            Undo::Impossible | Undo::Any => (None, None),
+        };
+        std::iter::successors(from, |lhs| lhs.plus(&N::from_u32(1)))
+            .take_while(move |lhs| to.as_ref().is_none_or(|to| lhs <= to))
+    }
+}
+
+/// The power of ten to shift `lhs` by to concatenate `rhs`
+fn shift<N: Number>(rhs: &N) -> Option<N> {
+    N::from_u32(10).power(&N::from_u32(rhs.digits()))
+}
+
+impl Operator {
+    /// How tightly the operator binds in [Mode::Precedence], `||` joins digits so it binds
+    /// tightest
+    fn precedence(self) -> u8 {
+        match self {
+            Operator::Add | Operator::Sub => 1,
+            Operator::Mul | Operator::Div => 2,
+            Operator::Pow => 3,
+            Operator::Concat => 4,
+        }
+    }
+
+    /// The result is at least as large as both sides, for parts of at least one
+    fn growing(self) -> bool {
+        matches!(self, Operator::Add | Operator::Mul | Operator::Concat)
+    }
+}
+
+/// How the operators of an equation combine
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+enum Mode {
+    /// Strictly left to right, as in the puzzle
+    #[default]
+    LeftToRight,
+    /// Tighter binding operators first, as in [Operator::precedence], then left to right
+    Precedence,
+    /// Any grouping of the parts with parentheses
+    Grouped,
 }
 
 impl From<usize> for Operator {
     fn from(value: usize) -> Self {
-        match value {
-            0 => Operator::Add,
-            1 => Operator::Mul,
-            _ => panic!("Unsupported operator"),
+        match Operator::ALL.get(value) {
+            Some(operator) => *operator,
+            None => panic!("Unsupported operator"),
         }
     }
 }
 
-impl Add<usize> for &Operator {
-    type Output = (bool, Operator);
+impl Display for Operator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let symbol = match self {
+            Operator::Add => "+",
+            Operator::Mul => "*",
+            Operator::Concat => "||",
+            Operator::Sub => "-",
+            Operator::Div => "/",
+            Operator::Pow => "^",
+        };
+        write!(f, "{symbol}")
+    }
+}
+
+impl FromStr for Operator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Operator::ALL
+            .into_iter()
+            .find(|operator| operator.to_string() == s)
+            .ok_or_else(|| format!("unknown operator {s}"))
+    }
+}
 
-    fn add(self, rhs: usize) -> Self::Output {
-        if rhs != 1 {
-            todo!("We'll only increment by 1")
+/// The operators allowed between the parts, in the order they are tried
+#[derive(Clone, Debug, PartialEq)]
+struct Operators(Vec<Operator>);
+
+impl Operators {
+    fn part_1() -> Self {
+        Self(vec![Operator::Add, Operator::Mul])
+    }
+
+    fn part_2() -> Self {
+        Self(vec![Operator::Add, Operator::Mul, Operator::Concat])
+    }
+
+    fn register(&mut self, operator: Operator) {
+        if !self.0.contains(&operator) {
+            self.0.push(operator);
         }
-        match self {
-            Operator::Add => (false, Operator::Mul),
-            Operator::Mul => (false, Operator::Concat),
-            Operator::Concat => (true, Operator::Add),
+    }
+
+    /// The operator after `operator`, and whether it wrapped around to the first
+    fn next(&self, operator: Operator) -> (bool, Operator) {
+        let idx = self.0.iter().position(|o| *o == operator).unwrap_or(0);
+        match self.0.get(idx + 1) {
+            Some(next) => (false, *next),
+            None => (true, self.0[0]),
+        }
+    }
+
+    /// Can some choice of operators combine `parts` at all, without leaving the naturals or
+    /// overflowing
+    fn evaluable<N: Number>(&self, parts: &[N]) -> bool {
+        let Some((first, rest)) = parts.split_first() else {
+            return false;
+        };
+        let mut values = vec![first.clone()];
+        for rhs in rest {
+            values = values
+                .iter()
+                .flat_map(|lhs| self.0.iter().filter_map(|o| o.apply(lhs, rhs)))
+                .collect();
+            values.sort_unstable();
+            values.dedup();
         }
+        !values.is_empty()
     }
 }
 
 #[derive(Debug, PartialEq)]
 struct Operation(Vec<Operator>);
 
-impl AddAssign<usize> for Operation {
-    fn add_assign(&mut self, rhs: usize) {
-        if rhs != 1 {
-            todo!("We'll only increment by 1")
-        }
-
+impl Operation {
+    /// Step to the next combination, false once every combination is done
+    fn next(&mut self, operators: &Operators) -> bool {
         for idx in 0..self.0.len() {
-            let (overflow, opp) = &self.0[idx] + 1;
+            let (overflow, opp) = operators.next(self.0[idx]);
             self.0[idx] = opp;
             if !overflow {
-                break;
+                return true;
             }
         }
+        false
     }
 }
 
-impl Operation {
-    fn max(&self) -> bool {
-        self.0 == vec![Operator::Concat; self.0.len()]
-    }
+struct Equation<N = usize> {
+    result: N,
+    parts: Vec<N>,
 }
 
-struct Equation {
-    result: usize,
-    parts: Vec<usize>,
+/// Parse a number, too large ones need `--big`
+fn number<N: Number>(value: &str) -> N {
+    value
+        .parse()
+        .unwrap_or_else(|err| panic!("{value}: {err:?}, try --big"))
 }
 
-impl From<&str> for Equation {
+impl<N: Number> From<&str> for Equation<N> {
     fn from(value: &str) -> Self {
         match value.splitn(2, ':').collect::<Vec<_>>()[..] {
             [expected_result, rest] => {
-                let parts = rest.trim().split(' ').map(|f| f.parse().unwrap()).collect();
+                let parts = rest.trim().split(' ').map(number).collect();
                 Equation {
-                    result: expected_result.parse().unwrap(),
+                    result: number(expected_result.trim()),
                     parts,
                 }
             }
@@ -78,38 +375,293 @@ impl From<&str> for Equation {
     }
 }
 
-impl Equation {
-    fn valid(&self) -> bool {
-        let mut operations = Operation(vec![Operator::Add; self.parts.len() - 1]);
+/// Can `parts` be combined into `target`, working from the last part back to the first
+///
+/// Each operator is undone on the target: `+` by subtracting, `*` by dividing and `||` by
+/// stripping the digits, dropping the branches where that isn't possible.
+fn reachable<N: Number>(target: &N, parts: &[N], operators: &Operators) -> bool {
+    let Some((last, rest)) = parts.split_last() else {
+        return false;
+    };
+    if rest.is_empty() {
+        return target == last;
+    }
+    operators
+        .0
+        .iter()
+        .any(|operator| match operator.undo(target, last) {
+            Undo::Any => operators.evaluable(rest),
+            undo => undo.values().any(|lhs| reachable(&lhs, rest, operators)),
+        })
+}
+
+/// Every choice of operators combining `parts` into `target`, searched right to left like
+/// [reachable], until `found` breaks
+///
+/// `chosen` are the operators after `parts`, last one first.
+fn solve<N: Number>(
+    target: &N,
+    parts: &[N],
+    operators: &Operators,
+    chosen: &mut Vec<Operator>,
+    found: &mut dyn FnMut(Operation) -> ControlFlow<()>,
+) -> ControlFlow<()> {
+    let Some((last, rest)) = parts.split_last() else {
+        return ControlFlow::Continue(());
+    };
+    if rest.is_empty() {
+        if target == last {
+            return found(Operation(chosen.iter().rev().copied().collect()));
+        }
+        return ControlFlow::Continue(());
+    }
+    for operator in &operators.0 {
+        chosen.push(*operator);
+        match operator.undo(target, last) {
+            Undo::Impossible => {}
+            lhs @ Undo::Lhs { .. } => {
+                for lhs in lhs.values() {
+                    solve(&lhs, rest, operators, chosen, found)?;
+                }
+            }
+            Undo::Any => {
+                // every way to combine the rest works
+                let mut operation = Operation(vec![operators.0[0]; rest.len() - 1]);
+                loop {
+                    if evaluate(rest, &operation.0).is_some() {
+                        let mut all = operation.0.clone();
+                        all.extend(chosen.iter().rev());
+                        found(Operation(all))?;
+                    }
+                    if !operation.next(operators) {
+                        break;
+                    }
+                }
+            }
+        }
+        chosen.pop();
+    }
+    ControlFlow::Continue(())
+}
+
+/// Left to right, `None` if a step leaves the naturals
+fn evaluate<N: Number>(parts: &[N], operations: &[Operator]) -> Option<N> {
+    let mut lhs = parts[0].clone();
+    for (idx, rhs) in parts[1..].iter().enumerate() {
+        lhs = operations[idx].apply(&lhs, rhs)?;
+    }
+    Some(lhs)
+}
+
+/// An equation with the operators that make it true
+struct Solution<'a, N = usize> {
+    equation: &'a Equation<N>,
+    operation: Operation,
+}
+
+impl<N: Number> Display for Solution<'_, N> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} = {}", self.equation.result, self.equation.parts[0])?;
+        for (operator, part) in self.operation.0.iter().zip(&self.equation.parts[1..]) {
+            write!(f, " {operator} {part}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Can `parts` be combined into `target` with [Mode::Precedence], choosing operators left to
+/// right
+///
+/// `pending` are the left hand sides still waiting for tighter binding operators to their
+/// right, and `current` the value after them.
+fn reachable_precedence<N: Number>(
+    target: &N,
+    parts: &[N],
+    operators: &Operators,
+    pending: &[(N, Operator)],
+    current: N,
+) -> bool {
+    let Some((next, rest)) = parts.split_first() else {
+        let mut value = Some(current);
+        for (lhs, operator) in pending.iter().rev() {
+            value = value.and_then(|value| operator.apply(lhs, &value));
+        }
+        return value.as_ref() == Some(target);
+    };
+    // without `-` the sum of the finished terms never comes back down
+    let growing = !operators.0.contains(&Operator::Sub);
+    operators.0.iter().any(|operator| {
+        // finish what binds at least as tight as the operator
+        let mut pending = pending.to_vec();
+        let mut current = Some(current.clone());
+        while let Some((lhs, left)) =
+            pending.pop_if(|(_, left)| left.precedence() >= operator.precedence())
+        {
+            current = current.and_then(|value| left.apply(&lhs, &value));
+        }
+        let Some(current) = current else {
+            return false;
+        };
+        if let [(sum, Operator::Add), ..] = &pending[..] {
+            if growing && sum > target {
+                return false;
+            }
+        }
+        pending.push((current, *operator));
+        reachable_precedence(target, rest, operators, &pending, next.clone())
+    })
+}
+
+/// Can `parts` be grouped into `target` with parentheses, using any operator between groups
+fn reachable_grouped<N: Number>(target: &N, parts: &[N], operators: &Operators) -> bool {
+    // Values too large can be dropped when they only grow
+    let one = N::from_u32(1);
+    let growing = operators.0.iter().all(|o| o.growing()) && parts.iter().all(|part| *part >= one);
+    // `values[start][len - 1]` are the values of `parts[start..start + len]`
+    let mut values: Vec<Vec<BTreeSet<N>>> = parts
+        .iter()
+        .map(|part| vec![BTreeSet::from([part.clone()])])
+        .collect();
+    // The whole equation only needs checking against the target, working back from the right
+    for len in 2..parts.len() {
+        for start in 0..=parts.len() - len {
+            let mut group = BTreeSet::new();
+            for split in 1..len {
+                let left = &values[start][split - 1];
+                let right = &values[start + split][len - split - 1];
+                for lhs in left {
+                    for rhs in right {
+                        for operator in &operators.0 {
+                            match operator.apply(lhs, rhs) {
+                                Some(value) if growing && value > *target => {}
+                                Some(value) => {
+                                    group.insert(value);
+                                }
+                                None => {}
+                            }
+                        }
+                    }
+                }
+            }
+            values[start].push(group);
+        }
+    }
+    let Some(last) = parts.len().checked_sub(1) else {
+        return false;
+    };
+    if last == 0 {
+        return parts[0] == *target;
+    }
+    (1..=last).any(|split| {
+        let left = &values[0][split - 1];
+        let right = &values[split][last - split];
+        right.iter().any(|rhs| {
+            operators
+                .0
+                .iter()
+                .any(|operator| match operator.undo(target, rhs) {
+                    Undo::Impossible => false,
+                    Undo::Lhs { from, to: Some(to) } => left.range(from..=to).next().is_some(),
+                    Undo::Lhs { from, to: None } => left.range(from..).next().is_some(),
+                    Undo::Any => !left.is_empty(),
+                })
+        })
+    })
+}
+
+impl<N: Number> Equation<N> {
+    fn valid(&self, operators: &Operators, mode: Mode) -> bool {
+        match mode {
+            Mode::LeftToRight => reachable(&self.result, &self.parts, operators),
+            Mode::Precedence => {
+                let Some((first, rest)) = self.parts.split_first() else {
+                    return false;
+                };
+                reachable_precedence(&self.result, rest, operators, &[], first.clone())
+            }
+            Mode::Grouped => reachable_grouped(&self.result, &self.parts, operators),
+        }
+    }
+
+    /// The first choice of operators that makes the equation true
+    fn solution(&self, operators: &Operators) -> Option<Solution<'_, N>> {
+        let mut solution = None;
+        let _ = solve(
+            &self.result,
+            &self.parts,
+            operators,
+            &mut Vec::new(),
+            &mut |operation| {
+                solution = Some(operation);
+                ControlFlow::Break(())
+            },
+        );
+        solution.map(|operation| Solution {
+            equation: self,
+            operation,
+        })
+    }
+
+    /// Every choice of operators that makes the equation true
+    fn solutions(&self, operators: &Operators) -> Vec<Solution<'_, N>> {
+        let mut solutions = Vec::new();
+        let _ = solve(
+            &self.result,
+            &self.parts,
+            operators,
+            &mut Vec::new(),
+            &mut |operation| {
+                solutions.push(Solution {
+                    equation: self,
+                    operation,
+                });
+                ControlFlow::Continue(())
+            },
+        );
+        solutions
+    }
+
+    fn solution_count(&self, operators: &Operators) -> usize {
+        let mut count = 0;
+        let _ = solve(
+            &self.result,
+            &self.parts,
+            operators,
+            &mut Vec::new(),
+            &mut |_| {
+                count += 1;
+                ControlFlow::Continue(())
+            },
+        );
+        count
+    }
+
+    /// Try every combination of operators, the slow way to check [Equation::valid]
+    fn valid_brute(&self, operators: &Operators) -> bool {
+        let mut operations = Operation(vec![operators.0[0]; self.parts.len() - 1]);
 
         loop {
             if self.test_opps(&operations.0) {
                 return true;
             };
-            if operations.max() {
+            if !operations.next(operators) {
                 break;
             }
-            operations += 1;
         }
 
         false
     }
 
     fn test_opps(&self, operations: &[Operator]) -> bool {
-        println!("{:?} - {operations:?}", self.parts);
-        let mut lhs = self.parts[0];
-        for (idx, rhs) in self.parts[1..].iter().enumerate() {
-            lhs = match operations[idx] {
-                Operator::Add => lhs + rhs,
-                Operator::Mul => lhs * rhs,
-                Operator::Concat => format!("{lhs}{rhs}").parse().unwrap(),
-            };
-        }
-        lhs == self.result
+        evaluate(&self.parts, operations).as_ref() == Some(&self.result)
     }
 }
 
 fn mangle(data: &str) -> Vec<Equation> {
+    parse(data)
+}
+
+fn parse<N: Number>(data: &str) -> Vec<Equation<N>> {
     let mut result = Vec::new();
     for line in data.lines() {
         if line.trim().is_empty() {
@@ -120,25 +672,123 @@ fn mangle(data: &str) -> Vec<Equation> {
     result
 }
 
-fn puzzle_1(eqs: &[Equation]) -> usize {
+/// Sum of the results of the equations that can be made true
+fn calibrate<N: Number>(eqs: &[Equation<N>], operators: &Operators, mode: Mode) -> N {
     eqs.iter()
-        .filter(|f| f.valid())
-        .fold(0, |acc, e| acc + e.result)
+        .filter(|f| f.valid(operators, mode))
+        .fold(N::from_u32(0), |acc, e| {
+            acc.plus(&e.result).expect("the sum overflows, try --big")
+        })
+}
+
+fn puzzle_1(eqs: &[Equation]) -> usize {
+    calibrate(eqs, &Operators::part_1(), Mode::LeftToRight)
+}
+
+fn puzzle_2(eqs: &[Equation]) -> usize {
+    calibrate(eqs, &Operators::part_2(), Mode::LeftToRight)
 }
 
 fn main() {
     let input = std::fs::read_to_string("input.txt").unwrap();
+    let args = std::env::args().collect::<Vec<_>>();
 
-    let data = mangle(&input);
-    println!("{}", puzzle_1(&data));
+    // `--big` for numbers of any size
+    if args.iter().any(|arg| arg == "--big") {
+        let data = parse::<BigUint>(&input);
+        println!(
+            "{}",
+            calibrate(&data, &Operators::part_1(), Mode::LeftToRight)
+        );
+        println!(
+            "{}",
+            calibrate(&data, &Operators::part_2(), Mode::LeftToRight)
+        );
+        options(&data, &args);
+    } else {
+        let data = mangle(&input);
+        println!("{}", puzzle_1(&data));
+        println!("{}", puzzle_2(&data));
+        options(&data, &args);
+    }
+}
+
+fn options<N: Number>(data: &[Equation<N>], args: &[String]) {
+    // `--precedence` has `*` bind before `+`, `--grouped` allows any parentheses. Every
+    // grouping of the longer lines with `||` makes too many values, so that is only part 1
+    let mut mode = Mode::LeftToRight;
+    for arg in args {
+        match arg.as_str() {
+            "--precedence" => mode = Mode::Precedence,
+            "--grouped" => mode = Mode::Grouped,
+            _ => {}
+        }
+    }
+    if mode != Mode::LeftToRight {
+        println!("{} ({mode:?})", calibrate(data, &Operators::part_1(), mode));
+        if mode == Mode::Precedence {
+            println!("{} ({mode:?})", calibrate(data, &Operators::part_2(), mode));
+        }
+    }
+    // `--register=-,/,^` adds operators to those of part 2, `-` and `/` can't prune much
+    // so the search gets a lot slower
+    for arg in args {
+        if let Some(symbols) = arg.strip_prefix("--register=") {
+            let mut operators = Operators::part_2();
+            for symbol in symbols.split(',') {
+                operators.register(symbol.parse().unwrap());
+            }
+            println!("{} ({operators:?})", calibrate(data, &operators, mode));
+        }
+    }
+    // `--solutions` shows how each equation is made true with the operators of part 2 and
+    // how many ways there are, `--all-solutions` lists every way
+    let all = args.iter().any(|arg| arg == "--all-solutions");
+    if all || args.iter().any(|arg| arg == "--solutions") {
+        let operators = Operators::part_2();
+        for equation in data {
+            if all {
+                for solution in equation.solutions(&operators) {
+                    println!("{solution}");
+                }
+            } else if let Some(solution) = equation.solution(&operators) {
+                let count = equation.solution_count(&operators);
+                println!("{solution} ({count} solutions)");
+            }
+        }
+    }
+    // `--brute` checks the answers against trying every combination
+    if args.iter().any(|arg| arg == "--brute") {
+        for operators in [Operators::part_1(), Operators::part_2()] {
+            let brute = data
+                .iter()
+                .filter(|f| f.valid_brute(&operators))
+                .fold(N::from_u32(0), |acc, e| acc.plus(&e.result).unwrap());
+            println!("{brute} (brute force)");
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
     use crate::puzzle_1;
+    use crate::puzzle_2;
+    use crate::Mode;
     use crate::Operation;
     use crate::Operator::Add;
+    use crate::Operator::Concat;
     use crate::Operator::Mul;
+    use crate::Operators;
+
+    /// Pseudo random numbers for the random tests, the same on every run
+    fn random(mut seed: u64) -> impl FnMut() -> usize {
+        move || {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) as usize
+        }
+    }
 
     #[test]
     fn test_eval() {
@@ -155,31 +805,347 @@ mod test {
         "#;
 
         let data = crate::mangle(input);
-        let x = data.iter().map(|f| f.valid()).collect::<Vec<bool>>();
+        let x = data
+            .iter()
+            .map(|f| f.valid(&Operators::part_1(), Mode::LeftToRight))
+            .collect::<Vec<bool>>();
         assert_eq!(
             x,
             vec![true, true, false, false, false, false, false, false, true]
         );
 
         assert_eq!(puzzle_1(&data), 3749);
+
+        let x = data
+            .iter()
+            .map(|f| f.valid(&Operators::part_2(), Mode::LeftToRight))
+            .collect::<Vec<bool>>();
+        assert_eq!(
+            x,
+            vec![true, true, false, true, true, false, true, false, true]
+        );
+        assert_eq!(puzzle_2(&data), 11387);
     }
 
     #[test]
     fn test_operation() {
+        let operators = Operators::part_1();
         let mut opp = Operation(vec![Add; 4]);
-        opp += 1;
+        assert!(opp.next(&operators));
         assert_eq!(opp, Operation(vec![Mul, Add, Add, Add]));
-        opp += 1;
+        assert!(opp.next(&operators));
         assert_eq!(opp, Operation(vec![Add, Mul, Add, Add]));
-        opp += 1;
+        assert!(opp.next(&operators));
         assert_eq!(opp, Operation(vec![Mul, Mul, Add, Add]));
-        opp += 1;
+        assert!(opp.next(&operators));
         assert_eq!(opp, Operation(vec![Add, Add, Mul, Add]));
-        opp += 1;
+        assert!(opp.next(&operators));
         assert_eq!(opp, Operation(vec![Mul, Add, Mul, Add]));
 
         let mut opp = Operation(vec![Mul; 4]);
-        opp += 1;
+        assert!(!opp.next(&operators));
         assert_eq!(opp, Operation(vec![Add, Add, Add, Add]));
+
+        let operators = Operators::part_2();
+        let mut opp = Operation(vec![Mul, Concat]);
+        assert!(opp.next(&operators));
+        assert_eq!(opp, Operation(vec![Concat, Concat]));
+        assert!(!opp.next(&operators));
+        assert_eq!(opp, Operation(vec![Add, Add]));
+    }
+
+    #[test]
+    fn test_operators() {
+        use crate::Operator::{Div, Pow, Sub};
+
+        assert_eq!(crate::Operator::from(2), Concat);
+        assert_eq!(Concat.apply(&12usize, &345), Some(12345));
+        assert_eq!(Concat.apply(&0usize, &0), Some(0));
+        assert_eq!(Sub.apply(&3usize, &5), None);
+        assert_eq!(Div.apply(&7usize, &2), Some(3));
+        assert_eq!(Div.apply(&7usize, &0), None);
+        assert_eq!(Pow.apply(&3usize, &4), Some(81));
+        assert_eq!("||".parse(), Ok(Concat));
+
+        let mut operators = Operators::part_2();
+        operators.register(Sub);
+        operators.register(Sub);
+        assert_eq!(operators, Operators(vec![Add, Mul, Concat, Sub]));
+
+        let data = crate::mangle("1: 12 5 2\n81: 3 4\n3: 7 2\n");
+        let operators = Operators(vec![Sub, Div, Pow]);
+        assert!(data.iter().all(|f| f.valid(&operators, Mode::LeftToRight)));
+        assert!(!data
+            .iter()
+            .any(|f| f.valid(&Operators::part_2(), Mode::LeftToRight)));
+    }
+
+    #[test]
+    fn test_reachable() {
+        let data = crate::mangle(
+            r#"
+0: 5 0
+5: 5 0
+0: 0 0 0
+156: 15 6
+10: 1 0
+"#,
+        );
+        let x = data
+            .iter()
+            .map(|f| f.valid(&Operators::part_1(), Mode::LeftToRight))
+            .collect::<Vec<bool>>();
+        assert_eq!(x, vec![true, true, true, false, false]);
+
+        // Concatenation strips the digits, zero has one
+        let concat = Operators::part_2();
+        assert!(crate::reachable(&156usize, &[15, 6], &concat));
+        assert!(crate::reachable(&7290usize, &[6, 8, 6, 15], &concat));
+        assert!(crate::reachable(&10usize, &[1, 0], &concat));
+        assert!(!crate::reachable(&100usize, &[1, 0], &concat));
+
+        // Small pseudo random equations give the same answer as every combination
+        let mut next = random(3);
+        for _ in 0..4000 {
+            // any non empty set of operators
+            let mask = 1 + next() % 63;
+            let operators = Operators(
+                (0..6)
+                    .filter(|idx| mask & (1 << idx) != 0)
+                    .map(crate::Operator::from)
+                    .collect(),
+            );
+            let parts = (0..1 + next() % 4).map(|_| next() % 6).collect::<Vec<_>>();
+            let result = match next() % 2 {
+                0 => next() % 200,
+                _ => parts.iter().sum(),
+            };
+            let equation = crate::Equation { result, parts };
+            assert_eq!(
+                equation.valid(&operators, Mode::LeftToRight),
+                equation.valid_brute(&operators),
+                "{result}: {:?} {operators:?}",
+                equation.parts
+            );
+
+            // and the same solutions
+            let mut brute = Vec::new();
+            let mut operation = Operation(vec![operators.0[0]; equation.parts.len() - 1]);
+            loop {
+                if equation.test_opps(&operation.0) {
+                    brute.push(operation.0.clone());
+                }
+                if !operation.next(&operators) {
+                    break;
+                }
+            }
+            let mut solutions = equation
+                .solutions(&operators)
+                .into_iter()
+                .map(|solution| solution.operation.0)
+                .collect::<Vec<_>>();
+            brute.sort_by_key(|operation| format!("{operation:?}"));
+            solutions.sort_by_key(|operation| format!("{operation:?}"));
+            assert_eq!(solutions, brute, "{result}: {:?}", equation.parts);
+            assert_eq!(equation.solution_count(&operators), brute.len());
+        }
+    }
+
+    /// Evaluate with precedence, splitting at the last of the loosest operators
+    fn evaluate_precedence(parts: &[usize], operations: &[crate::Operator]) -> Option<usize> {
+        let Some(loosest) = operations.iter().map(|o| o.precedence()).min() else {
+            return Some(parts[0]);
+        };
+        let split = operations
+            .iter()
+            .rposition(|o| o.precedence() == loosest)
+            .unwrap();
+        let lhs = evaluate_precedence(&parts[..=split], &operations[..split])?;
+        let rhs = evaluate_precedence(&parts[split + 1..], &operations[split + 1..])?;
+        operations[split].apply(&lhs, &rhs)
+    }
+
+    /// Every value of every grouping
+    fn groupings(parts: &[usize], operators: &Operators) -> Vec<usize> {
+        if parts.len() == 1 {
+            return parts.to_vec();
+        }
+        let mut values = Vec::new();
+        for split in 1..parts.len() {
+            for lhs in groupings(&parts[..split], operators) {
+                for rhs in groupings(&parts[split..], operators) {
+                    values.extend(operators.0.iter().filter_map(|o| o.apply(&lhs, &rhs)));
+                }
+            }
+        }
+        values
+    }
+
+    #[test]
+    fn test_modes() {
+        let input = r#"
+3267: 81 40 27
+292: 11 6 16 20
+1161: 81 40 27
+7: 1 2 3
+"#;
+        let data = crate::mangle(input);
+        let operators = Operators::part_1();
+        let valid = |mode| {
+            data.iter()
+                .map(|f| f.valid(&operators, mode))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(valid(Mode::LeftToRight), [true, true, false, false]);
+        assert_eq!(valid(Mode::Precedence), [true, false, true, true]);
+        assert_eq!(valid(Mode::Grouped), [true, true, true, true]);
+
+        // Small pseudo random equations give the same answer as every combination
+        let mut next = random(11);
+        for _ in 0..3000 {
+            let mask = 1 + next() % 63;
+            let operators = Operators(
+                (0..6)
+                    .filter(|idx| mask & (1 << idx) != 0)
+                    .map(crate::Operator::from)
+                    .collect(),
+            );
+            let parts = (0..1 + next() % 4).map(|_| next() % 6).collect::<Vec<_>>();
+            let result = match next() % 2 {
+                0 => next() % 100,
+                _ => parts.iter().sum(),
+            };
+            let mut precedence = false;
+            let mut operation = Operation(vec![operators.0[0]; parts.len() - 1]);
+            loop {
+                precedence |= evaluate_precedence(&parts, &operation.0) == Some(result);
+                if !operation.next(&operators) {
+                    break;
+                }
+            }
+            let grouped = groupings(&parts, &operators).contains(&result);
+            let equation = crate::Equation { result, parts };
+            assert_eq!(
+                equation.valid(&operators, Mode::Precedence),
+                precedence,
+                "{result}: {:?} {operators:?}",
+                equation.parts
+            );
+            assert_eq!(
+                equation.valid(&operators, Mode::Grouped),
+                grouped,
+                "{result}: {:?} {operators:?}",
+                equation.parts
+            );
+        }
+    }
+
+    #[test]
+    fn test_overflow() {
+        let data = crate::mangle(
+            r#"
+18446744073709551615: 18446744073709551615 1
+18446744073709551615: 1844674407370955161 5
+18446744073709551614: 18446744073709551615 2 2
+"#,
+        );
+        let operators = Operators::part_2();
+        assert!(data[0].valid(&operators, Mode::LeftToRight));
+        assert!(data[1].valid(&operators, Mode::LeftToRight));
+        // every way overflows, without a panic
+        assert!(!data[2].valid(&operators, Mode::LeftToRight));
+        assert!(!data[2].valid_brute(&operators));
+        assert_eq!(Concat.apply(&1844674407370955161usize, &6), None);
+        assert_eq!(Concat.apply(&1usize, &10000000000000000000), None);
+
+        // Times zero would bring it back down, but every way there overflows first
+        let data = crate::mangle(
+            r#"
+0: 18446744073709551615 2 0
+0: 18446744073709551615 0 0
+"#,
+        );
+        let part_1 = Operators::part_1();
+        assert!(!data[0].valid(&part_1, Mode::LeftToRight));
+        assert!(data[0].solution(&part_1).is_none());
+        assert_eq!(data[0].solution_count(&part_1), 0);
+        assert!(!data[0].valid_brute(&part_1));
+        assert!(data[1].valid(&part_1, Mode::LeftToRight));
+        assert_eq!(
+            data[1].solution_count(&part_1),
+            data[1].solutions(&part_1).len()
+        );
+        assert!(data[1].valid_brute(&part_1));
+
+        // Roots are exact past the precision of a float
+        use crate::Number;
+        let large = (1usize << 60) + 100;
+        assert_eq!(large.root(&1), Some(large));
+        assert_eq!((1usize << 62).root(&2), Some(1 << 31));
+        assert_eq!(((1usize << 31) - 1).pow(2).root(&2), Some((1 << 31) - 1));
+        assert_eq!(((1usize << 62) + 1).root(&2), None);
+        assert_eq!(3usize.pow(40).root(&40), Some(3));
+        assert_eq!(usize::MAX.root(&64), None);
+        let mut pow = Operators::part_2();
+        pow.register(crate::Operator::Pow);
+        let equation = crate::Equation {
+            result: large,
+            parts: vec![large, 1],
+        };
+        assert!(equation.valid_brute(&pow));
+        assert!(equation.valid(&pow, Mode::LeftToRight));
+
+        // Big numbers go past it
+        let data = crate::parse::<num_bigint::BigUint>(
+            r#"
+36893488147419103230: 18446744073709551615 2
+18446744073709551615165: 18446744073709551615 165
+10: 3 7
+"#,
+        );
+        assert!(data[0].valid(&Operators::part_1(), Mode::LeftToRight));
+        assert!(!data[1].valid(&Operators::part_1(), Mode::LeftToRight));
+        assert_eq!(
+            data[1].solution(&operators).unwrap().to_string(),
+            "18446744073709551615165 = 18446744073709551615 || 165"
+        );
+        let big = |value: &str| value.parse::<num_bigint::BigUint>().unwrap();
+        assert_eq!(
+            crate::calibrate(&data, &operators, crate::Mode::LeftToRight),
+            big("36893488147419103230") + big("18446744073709551615165") + big("10")
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "try --big")]
+    fn test_too_large() {
+        crate::mangle("36893488147419103230: 18446744073709551615 2");
+    }
+
+    #[test]
+    fn test_solutions() {
+        let data = crate::mangle("190: 10 19\n3267: 81 40 27\n7290: 6 8 6 15\n83: 17 5\n");
+        let show = |idx: usize, operators: &Operators| {
+            data[idx]
+                .solutions(operators)
+                .iter()
+                .map(|solution| solution.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(show(0, &Operators::part_1()), ["190 = 10 * 19"]);
+        assert_eq!(
+            show(1, &Operators::part_1()),
+            ["3267 = 81 * 40 + 27", "3267 = 81 + 40 * 27"]
+        );
+        assert_eq!(show(2, &Operators::part_2()), ["7290 = 6 * 8 || 6 * 15"]);
+        assert!(show(2, &Operators::part_1()).is_empty());
+
+        assert_eq!(data[1].solution_count(&Operators::part_1()), 2);
+        assert_eq!(data[3].solution_count(&Operators::part_2()), 0);
+        assert!(data[3].solution(&Operators::part_2()).is_none());
+        assert_eq!(
+            data[0].solution(&Operators::part_2()).unwrap().to_string(),
+            "190 = 10 * 19"
+        );
     }
 }
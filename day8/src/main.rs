@@ -1,6 +1,7 @@
 use std::{
     collections::HashSet,
-    ops::{Add, Sub},
+    ops::{Add, Div, Sub},
+    str::FromStr,
 };
 
 #[derive(Debug, PartialEq, Eq)]
@@ -34,6 +35,74 @@ impl Sub for &Pos {
     }
 }
 
+impl Div<isize> for &Pos {
+    type Output = Pos;
+
+    fn div(self, rhs: isize) -> Self::Output {
+        Pos(self.0 / rhs, self.1 / rhs)
+    }
+}
+
+impl Pos {
+    /// The smallest step in the same direction that still lands on the grid
+    fn reduced(&self) -> Pos {
+        let gcd = gcd(self.0.unsigned_abs(), self.1.unsigned_abs()).max(1);
+        self / gcd as isize
+    }
+
+    /// `None` if it overflows
+    fn checked_add(&self, rhs: &Pos) -> Option<Pos> {
+        Some(Pos(self.0.checked_add(rhs.0)?, self.1.checked_add(rhs.1)?))
+    }
+
+    /// `None` if it overflows
+    fn checked_mul(&self, rhs: isize) -> Option<Pos> {
+        Some(Pos(self.0.checked_mul(rhs)?, self.1.checked_mul(rhs)?))
+    }
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// Which points in line with two antennas are anti-nodes
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Harmonics {
+    /// Points on either side where one antenna is twice as far away as the other (part 1)
+    Doubled,
+    /// Points a third of the way between the antennas, also twice as far from one as the other
+    Inside,
+    /// Every grid point in line with both antennas (part 2)
+    All,
+    /// Points `from` to `to` grid steps away from either antenna, in both directions
+    Multiples { from: usize, to: usize },
+}
+
+impl FromStr for Harmonics {
+    type Err = String;
+
+    /// `doubled`, `inside`, `all` or a range of multiples like `1..=3`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "doubled" => Ok(Harmonics::Doubled),
+            "inside" => Ok(Harmonics::Inside),
+            "all" => Ok(Harmonics::All),
+            range => {
+                let (from, to) = range
+                    .split_once("..=")
+                    .ok_or_else(|| format!("unknown harmonics {range}"))?;
+                let from = from.parse().map_err(|_| format!("bad start in {range}"))?;
+                let to = to.parse().map_err(|_| format!("bad end in {range}"))?;
+                Ok(Harmonics::Multiples { from, to })
+            }
+        }
+    }
+}
+
 impl Antenna {
     fn new(freq: char, row: usize, col: usize) -> Self {
         Antenna {
@@ -89,39 +158,67 @@ impl Antennas {
             && position.0 >= 0
             && position.1 >= 0
     }
-    fn anti_nodes(&self, lhs: &Antenna, rhs: &Antenna) -> Option<HashSet<Pos>> {
+    /// The anti-nodes of two antennas inside the map
+    /// returns None if the antennas are different frequencies
+    fn anti_nodes(
+        &self,
+        lhs: &Antenna,
+        rhs: &Antenna,
+        harmonics: Harmonics,
+    ) -> Option<HashSet<Pos>> {
+        let (from, to) = match harmonics {
+            Harmonics::Doubled => {
+                let (node_1, node_2) = lhs.anti_nodes(rhs)?;
+                return Some(
+                    [node_1, node_2]
+                        .into_iter()
+                        .filter(|f| self.valid(f))
+                        .collect(),
+                );
+            }
+            Harmonics::Inside => {
+                if lhs.freq != rhs.freq || lhs == rhs {
+                    return None;
+                }
+                // only on the grid when the difference divides by 3
+                let diff = &lhs.pos - &rhs.pos;
+                if diff.0 % 3 != 0 || diff.1 % 3 != 0 {
+                    return Some(HashSet::new());
+                }
+                let third = &diff / 3;
+                return Some(HashSet::from([&lhs.pos - &third, &rhs.pos + &third]));
+            }
+            Harmonics::All => (0, usize::MAX),
+            Harmonics::Multiples { from, to } => (from, to),
+        };
         if lhs.freq != rhs.freq || lhs == rhs {
             return None;
         }
-        let mut anti_nodes = HashSet::from([lhs.pos.clone(), rhs.pos.clone()]);
-        let diff = &lhs.pos - &rhs.pos;
-
-        // find the downstream nodes
-        let mut node = &lhs.pos + &diff;
-        loop {
-            if !self.valid(&node) {
-                break;
-            }
-            anti_nodes.insert(node.clone());
-            node = &node + &diff;
-        }
-
-        // find the downstream nodes
-        let mut node = &rhs.pos - &diff;
-        loop {
-            if !self.valid(&node) {
-                break;
+        // step by the gcd so no grid point in between is missed
+        let step = (&lhs.pos - &rhs.pos).reduced();
+        let mut anti_nodes = HashSet::new();
+        for antenna in [lhs, rhs] {
+            for direction in [1, -1] {
+                // once off the map the line never comes back, as do multiples too large to count
+                for k in from..=to {
+                    let Some(node) = isize::try_from(k)
+                        .ok()
+                        .and_then(|k| step.checked_mul(direction * k))
+                        .and_then(|offset| antenna.pos.checked_add(&offset))
+                        .filter(|node| self.valid(node))
+                    else {
+                        break;
+                    };
+                    anti_nodes.insert(node);
+                }
             }
-            anti_nodes.insert(node.clone());
-            node = &node - &diff;
         }
-
         Some(anti_nodes)
     }
 
     fn print(&self, anti_nodes: &HashSet<Pos>) {
         for row_idx in 0..=self.max_row {
-            let mut row = vec!['.'; self.max_row + 1];
+            let mut row = vec!['.'; self.max_col + 1];
             for ant in self.antennas.iter() {
                 // antennas are always inside the map
                 if ant.pos.0 as usize == row_idx {
@@ -141,72 +238,50 @@ impl Antennas {
     }
 }
 
-fn puzzle_1(antennas: &Antennas) -> usize {
+/// Every anti-node of every pair of antennas
+fn resonate(antennas: &Antennas, harmonics: Harmonics) -> HashSet<Pos> {
     let mut anti_nodes: HashSet<Pos> = HashSet::new();
 
     for ant in antennas.antennas.iter() {
         for other_ant in antennas.antennas.iter() {
-            match ant.anti_nodes(other_ant) {
-                None => {}
-                Some((pos_1, pos_2)) => {
-                    if antennas.valid(&pos_1) {
-                        anti_nodes.insert(pos_1);
-                    }
-                    if antennas.valid(&pos_2) {
-                        anti_nodes.insert(pos_2);
-                    }
-                }
+            if let Some(nodes) = antennas.anti_nodes(ant, other_ant, harmonics) {
+                anti_nodes.extend(nodes);
             }
         }
     }
-    // for node in anti_nodes.iter() {
-    //     println!("{node:?}");
-    // }
 
-    anti_nodes.len()
+    anti_nodes
 }
 
-fn puzzle_2(antennas: &Antennas) -> usize {
-    let mut anti_nodes: HashSet<Pos> = HashSet::new();
-
-    for ant in antennas.antennas.iter() {
-        for other_ant in antennas.antennas.iter() {
-            match antennas.anti_nodes(ant, other_ant) {
-                None => {}
-                Some(nodes) => {
-                    anti_nodes.extend(nodes);
-                }
-            }
-        }
-    }
-    // antennas.print(&anti_nodes);
-    // for node in anti_nodes.iter() {
-    //     println!("{node:?}");
-    // }
+fn puzzle_1(antennas: &Antennas) -> usize {
+    resonate(antennas, Harmonics::Doubled).len()
+}
 
-    anti_nodes.len()
+fn puzzle_2(antennas: &Antennas) -> usize {
+    resonate(antennas, Harmonics::All).len()
 }
 
 fn main() {
-    let input = r#"
-............
-........0...
-.....0......
-.......0....
-....0.......
-......A.....
-............
-............
-........A...
-.........A..
-............
-............
-        "#;
     let input = std::fs::read_to_string("input.txt").unwrap();
+    let args = std::env::args().collect::<Vec<_>>();
 
     let antennas = Antennas::new(&input);
     println!("{}", puzzle_1(&antennas));
     println!("{}", puzzle_2(&antennas));
+
+    // `--harmonics=doubled`, `inside`, `all` or a range like `1..=3` counts those
+    // anti-nodes, `--print` draws them on the map
+    let harmonics = args
+        .iter()
+        .find_map(|arg| arg.strip_prefix("--harmonics="))
+        .map(|harmonics| harmonics.parse::<Harmonics>().unwrap());
+    if let Some(harmonics) = harmonics {
+        println!("{} ({harmonics:?})", resonate(&antennas, harmonics).len());
+    }
+    if args.iter().any(|arg| arg == "--print") {
+        let harmonics = harmonics.unwrap_or(Harmonics::All);
+        antennas.print(&resonate(&antennas, harmonics));
+    }
 }
 
 #[cfg(test)]
@@ -274,6 +349,97 @@ mod test {
         assert_eq!(result, (Pos(4, 5), Pos(4, 2)));
     }
 
+    #[test]
+    fn test_harmonics() {
+        // The step between these is (2, 4) but (1, 2) is in line with both
+        let input = r#"
+a....
+.....
+....a
+.....
+.....
+"#;
+        let antennas = Antennas::new(input);
+        let (lhs, rhs) = (&antennas.antennas[0], &antennas.antennas[1]);
+        let nodes = |harmonics| {
+            let mut nodes = antennas
+                .anti_nodes(lhs, rhs, harmonics)
+                .unwrap()
+                .into_iter()
+                .collect::<Vec<_>>();
+            nodes.sort_by_key(|pos| (pos.0, pos.1));
+            nodes
+        };
+        assert_eq!(nodes(Harmonics::All), [Pos(0, 0), Pos(1, 2), Pos(2, 4)]);
+        assert_eq!(nodes(Harmonics::Doubled), []);
+        assert_eq!(nodes(Harmonics::Inside), []);
+        assert_eq!(nodes(Harmonics::Multiples { from: 1, to: 1 }), [Pos(1, 2)]);
+        assert_eq!(
+            nodes(Harmonics::Multiples { from: 0, to: 0 }),
+            [Pos(0, 0), Pos(2, 4)]
+        );
+
+        // A third of the way between is twice as far from one as the other
+        let input = r#"
+b......
+.......
+.......
+...b...
+.......
+.......
+.......
+"#;
+        let antennas = Antennas::new(input);
+        let (lhs, rhs) = (&antennas.antennas[0], &antennas.antennas[1]);
+        let nodes = |harmonics| {
+            let mut nodes = antennas
+                .anti_nodes(lhs, rhs, harmonics)
+                .unwrap()
+                .into_iter()
+                .collect::<Vec<_>>();
+            nodes.sort_by_key(|pos| (pos.0, pos.1));
+            nodes
+        };
+        assert_eq!(nodes(Harmonics::Doubled), [Pos(6, 6)]);
+        assert_eq!(nodes(Harmonics::Inside), [Pos(1, 1), Pos(2, 2)]);
+
+        assert_eq!("doubled".parse(), Ok(Harmonics::Doubled));
+        assert_eq!("inside".parse(), Ok(Harmonics::Inside));
+        assert_eq!("all".parse(), Ok(Harmonics::All));
+        assert_eq!("1..=3".parse(), Ok(Harmonics::Multiples { from: 1, to: 3 }));
+        assert!("1..3".parse::<Harmonics>().is_err());
+
+        // Multiples too large to step to are off the map
+        let huge = |from, to| {
+            antennas
+                .anti_nodes(lhs, rhs, Harmonics::Multiples { from, to })
+                .unwrap()
+        };
+        assert!(huge(isize::MAX as usize, usize::MAX).is_empty());
+        assert!(huge(isize::MAX as usize / 2, isize::MAX as usize).is_empty());
+        assert!(huge(usize::MAX - 1, usize::MAX).is_empty());
+        assert_eq!(huge(1, usize::MAX).len(), 7);
+
+        // Reducing the step doesn't change the puzzle
+        let input = r#"
+............
+........0...
+.....0......
+.......0....
+....0.......
+......A.....
+............
+............
+........A...
+.........A..
+............
+............
+        "#;
+        let antennas = Antennas::new(input);
+        assert_eq!(resonate(&antennas, Harmonics::Doubled).len(), 14);
+        assert_eq!(resonate(&antennas, Harmonics::All).len(), 34);
+    }
+
     #[test]
     fn test_pos_add() {
         let lhs = Pos(4, 4);